@@ -0,0 +1,49 @@
+// Element access for array inputs, which are packed row by row into 2D textures.
+// Every array input `name` comes with an `ivec3 name_layout` uniform holding the texture
+// width, the texture height and the array length.
+//
+//     uniform isampler2D counts;
+//     uniform ivec3 counts_layout;
+//     int count = wvr_buffer_fetch(counts, counts_layout, 12);
+//
+// Indices outside of the array read 0.
+
+ivec2 wvr_buffer_coord(ivec3 buffer_layout, int index) {
+    return ivec2(index % buffer_layout.x, index / buffer_layout.x);
+}
+
+bool wvr_buffer_contains(ivec3 buffer_layout, int index) {
+    return index >= 0 && index < buffer_layout.z;
+}
+
+// Float arrays
+float wvr_buffer_fetch(sampler2D buffer_texture, ivec3 buffer_layout, int index) {
+    if (!wvr_buffer_contains(buffer_layout, index)) {
+        return 0.0;
+    }
+    return texelFetch(buffer_texture, wvr_buffer_coord(buffer_layout, index), 0).r;
+}
+
+// Integer arrays
+int wvr_buffer_fetch(isampler2D buffer_texture, ivec3 buffer_layout, int index) {
+    if (!wvr_buffer_contains(buffer_layout, index)) {
+        return 0;
+    }
+    return texelFetch(buffer_texture, wvr_buffer_coord(buffer_layout, index), 0).r;
+}
+
+// Boolean and byte arrays
+uint wvr_buffer_fetch(usampler2D buffer_texture, ivec3 buffer_layout, int index) {
+    if (!wvr_buffer_contains(buffer_layout, index)) {
+        return 0u;
+    }
+    return texelFetch(buffer_texture, wvr_buffer_coord(buffer_layout, index), 0).r;
+}
+
+// Arrays of vectors, unused components read 0
+vec4 wvr_buffer_fetch_vector(sampler2D buffer_texture, ivec3 buffer_layout, int index) {
+    if (!wvr_buffer_contains(buffer_layout, index)) {
+        return vec4(0.0);
+    }
+    return texelFetch(buffer_texture, wvr_buffer_coord(buffer_layout, index), 0);
+}
@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+
+use glium::backend::Facade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::index::IndexBuffer;
+use glium::texture::Texture2d;
+use glium::texture::UncompressedFloatFormat;
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::Program;
+use glium::Surface;
+use glium::VertexBuffer;
+
+use crate::filter::{build_quad_buffers, Vertex};
+
+const DECAY_VERTEX_SHADER: &str = r#"
+#version 140
+
+in vec2 position;
+in vec2 tex_coords;
+
+out vec2 uv;
+
+void main() {
+    uv = tex_coords;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+const DECAY_FRAGMENT_SHADER: &str = r#"
+#version 140
+
+uniform sampler2D previous_frame;
+uniform float decay;
+uniform float quantization_step;
+
+in vec2 uv;
+
+out vec4 color;
+
+void main() {
+    vec4 faded = texture(previous_frame, uv) * decay;
+
+    // Rounding to the nearest step would keep small values from ever reaching 0
+    if (quantization_step > 0.0) {
+        faded = max(faded - quantization_step, 0.0);
+    }
+
+    color = faded;
+}
+"#;
+
+/// Copies the previous frame of an accumulating stage into the buffer it is about to draw
+/// over, fading every channel by a decay factor.
+pub struct DecayPass {
+    program: Program,
+    vertex_buffer: VertexBuffer<Vertex>,
+    index_buffer: IndexBuffer<u16>,
+}
+
+impl DecayPass {
+    pub fn new(display: &dyn Facade) -> Result<Self> {
+        let program =
+            Program::from_source(display, DECAY_VERTEX_SHADER, DECAY_FRAGMENT_SHADER, None)
+                .context("Failed to build decay program")?;
+        let (vertex_buffer, index_buffer) = build_quad_buffers(display)?;
+
+        Ok(Self {
+            program,
+            vertex_buffer,
+            index_buffer,
+        })
+    }
+
+    /// Writes `previous_frame` multiplied by `decay` into `target`, a decay of 1 copies it as is.
+    /// In 8 bits buffers one more step is subtracted so that faded values reach 0.
+    pub fn apply(
+        &self,
+        display: &dyn Facade,
+        previous_frame: &Texture2d,
+        target: &Texture2d,
+        buffer_format: UncompressedFloatFormat,
+        decay: f32,
+    ) -> Result<()> {
+        if decay >= 1.0 {
+            previous_frame
+                .as_surface()
+                .fill(&target.as_surface(), MagnifySamplerFilter::Nearest);
+            return Ok(());
+        }
+
+        let mut framebuffer = SimpleFrameBuffer::new(display, target)
+            .context("Failed to create target buffer for decay")?;
+
+        let quantization_step = match buffer_format {
+            UncompressedFloatFormat::U8U8U8U8 => 1.0f32 / 255.0,
+            _ => 0.0f32,
+        };
+
+        let uniforms = uniform! {
+            previous_frame: previous_frame
+                .sampled()
+                .minify_filter(MinifySamplerFilter::Nearest)
+                .magnify_filter(MagnifySamplerFilter::Nearest),
+            decay: decay.max(0.0),
+            quantization_step: quantization_step,
+        };
+
+        framebuffer
+            .draw(
+                &self.vertex_buffer,
+                &self.index_buffer,
+                &self.program,
+                &uniforms,
+                &Default::default(),
+            )
+            .context("Failed to apply decay to previous frame")
+    }
+}
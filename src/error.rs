@@ -0,0 +1,379 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use glium::program::ProgramChooserCreationError;
+use glium::program::ProgramCreationError;
+use glium::program::ShaderType;
+
+use crate::source_map::SourceMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Geometry,
+    TessellationControl,
+    TessellationEvaluation,
+    Compute,
+    Link,
+}
+
+impl From<ShaderType> for ShaderStage {
+    fn from(shader_type: ShaderType) -> Self {
+        match shader_type {
+            ShaderType::Vertex => ShaderStage::Vertex,
+            ShaderType::Fragment => ShaderStage::Fragment,
+            ShaderType::Geometry => ShaderStage::Geometry,
+            ShaderType::TesselationControl => ShaderStage::TessellationControl,
+            ShaderType::TesselationEvaluation => ShaderStage::TessellationEvaluation,
+            ShaderType::Compute => ShaderStage::Compute,
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::Fragment => "fragment",
+            ShaderStage::Geometry => "geometry",
+            ShaderStage::TessellationControl => "tessellation control",
+            ShaderStage::TessellationEvaluation => "tessellation evaluation",
+            ShaderStage::Compute => "compute",
+            ShaderStage::Link => "link",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A shader compilation or link failure, located as precisely as the driver allows.
+#[derive(Debug, Clone)]
+pub struct ShaderCompileError {
+    pub stage: ShaderStage,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+    pub source_line: Option<String>,
+}
+
+impl ShaderCompileError {
+    /// Extracts every error reported by the driver, mapped back to the original source files.
+    pub fn from_program_error(
+        error: &ProgramChooserCreationError,
+        vertex_text: &str,
+        fragment_text: &str,
+        vertex_source_map: &SourceMap,
+        fragment_source_map: &SourceMap,
+    ) -> Vec<Self> {
+        let (message, stage) = match error {
+            ProgramChooserCreationError::ProgramCreationError(
+                ProgramCreationError::CompilationError(message, shader_type),
+            ) => (message.as_str(), ShaderStage::from(*shader_type)),
+            e => {
+                return vec![Self {
+                    stage: ShaderStage::Link,
+                    file: None,
+                    line: None,
+                    column: None,
+                    message: e.to_string(),
+                    source_line: None,
+                }]
+            }
+        };
+
+        let (source_text, source_map) = match stage {
+            ShaderStage::Vertex => (vertex_text, Some(vertex_source_map)),
+            ShaderStage::Fragment => (fragment_text, Some(fragment_source_map)),
+            _ => ("", None),
+        };
+
+        let error_list: Vec<Self> = message
+            .lines()
+            .filter_map(parse_log_line)
+            .filter(|entry| !entry.is_warning)
+            .map(|entry| {
+                let source_line = entry
+                    .line
+                    .checked_sub(1)
+                    .and_then(|line_index| source_text.lines().nth(line_index))
+                    .map(str::to_owned);
+
+                let (file, line) =
+                    match source_map.and_then(|source_map| source_map.resolve(entry.line)) {
+                        Some((file, line)) => (Some(file.to_path_buf()), line),
+                        None => (None, entry.line),
+                    };
+
+                Self {
+                    stage,
+                    file,
+                    line: Some(line),
+                    column: entry.column,
+                    message: entry.message,
+                    source_line,
+                }
+            })
+            .collect();
+
+        if error_list.is_empty() {
+            vec![Self {
+                stage,
+                file: None,
+                line: None,
+                column: None,
+                message: message.trim().to_owned(),
+                source_line: None,
+            }]
+        } else {
+            error_list
+        }
+    }
+}
+
+struct LogEntry {
+    line: usize,
+    column: Option<usize>,
+    message: String,
+    is_warning: bool,
+}
+
+fn parse_log_line(log_line: &str) -> Option<LogEntry> {
+    let log_line = log_line.trim();
+
+    parse_amd_log_line(log_line)
+        .or_else(|| parse_nvidia_log_line(log_line))
+        .or_else(|| parse_mesa_log_line(log_line))
+}
+
+/// Parses the `ERROR: 0:LINE: message` form used by AMD, Intel on Windows and Apple.
+fn parse_amd_log_line(log_line: &str) -> Option<LogEntry> {
+    let (is_warning, position_info) = if let Some(rest) = log_line.strip_prefix("ERROR:") {
+        (false, rest)
+    } else if let Some(rest) = log_line.strip_prefix("WARNING:") {
+        (true, rest)
+    } else {
+        return None;
+    };
+
+    let (source_index, rest) = position_info.trim_start().split_once(':')?;
+    source_index.parse::<usize>().ok()?;
+
+    let (line, message) = rest.split_once(':')?;
+
+    Some(LogEntry {
+        line: line.trim().parse().ok()?,
+        column: None,
+        message: message.trim().to_owned(),
+        is_warning,
+    })
+}
+
+/// Parses the `0(LINE) : error C0000: message` form used by NVIDIA.
+fn parse_nvidia_log_line(log_line: &str) -> Option<LogEntry> {
+    let (source_index, rest) = log_line.split_once('(')?;
+    source_index.trim().parse::<usize>().ok()?;
+
+    let (line, message) = rest.split_once(')')?;
+    let message = message.trim_start().strip_prefix(':')?.trim();
+
+    Some(LogEntry {
+        line: line.trim().parse().ok()?,
+        column: None,
+        message: message.to_owned(),
+        is_warning: message.starts_with("warning"),
+    })
+}
+
+/// Parses the `0:LINE(COLUMN): error: message` form used by Mesa.
+fn parse_mesa_log_line(log_line: &str) -> Option<LogEntry> {
+    let (source_index, rest) = log_line.split_once(':')?;
+    source_index.trim().parse::<usize>().ok()?;
+
+    let (position_info, message) = rest.split_once(':')?;
+    let (line, column) = match position_info.split_once('(') {
+        Some((line, column)) => (line, column.trim_end_matches(')').parse().ok()),
+        None => (position_info, None),
+    };
+    let message = message.trim();
+
+    Some(LogEntry {
+        line: line.trim().parse().ok()?,
+        column,
+        message: message.to_owned(),
+        is_warning: message.starts_with("warning"),
+    })
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} shader error", self.stage)?;
+
+        if let Some(file) = &self.file {
+            write!(f, " in {}", file.display())?;
+        }
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ", column {}", column)?;
+            }
+        }
+        writeln!(f)?;
+
+        if let Some(source_line) = &self.source_line {
+            writeln!(f, "{}", source_line)?;
+            writeln!(
+                f,
+                "{}^",
+                (0..self.column.unwrap_or(0))
+                    .map(|_| " ")
+                    .collect::<String>()
+            )?;
+        }
+
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ShaderCompileError {}
+
+/// All the errors reported while building a filter program.
+#[derive(Debug, Clone)]
+pub struct ProgramCompileError {
+    pub diagnostics: Vec<ShaderCompileError>,
+}
+
+impl fmt::Display for ProgramCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, diagnostic) in self.diagnostics.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProgramCompileError {}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use wvr_data::shader::Shader;
+
+    use crate::source_map::SourceComposer;
+    use crate::test_util::TextShader;
+
+    use super::*;
+
+    #[test]
+    fn parses_mesa_log_lines() {
+        let entry = parse_log_line("0:12(5): error: `foo' undeclared").unwrap();
+        assert_eq!(entry.line, 12);
+        assert_eq!(entry.column, Some(5));
+        assert_eq!(entry.message, "error: `foo' undeclared");
+        assert!(!entry.is_warning);
+
+        let entry = parse_log_line("0:3: warning: unused variable").unwrap();
+        assert_eq!(entry.line, 3);
+        assert_eq!(entry.column, None);
+        assert!(entry.is_warning);
+    }
+
+    #[test]
+    fn parses_nvidia_log_lines() {
+        let entry = parse_log_line("0(27) : error C1008: undefined variable \"foo\"").unwrap();
+        assert_eq!(entry.line, 27);
+        assert_eq!(entry.column, None);
+        assert_eq!(entry.message, "error C1008: undefined variable \"foo\"");
+        assert!(!entry.is_warning);
+
+        let entry = parse_log_line("0(4) : warning C7022: unrecognized profile").unwrap();
+        assert_eq!(entry.line, 4);
+        assert!(entry.is_warning);
+    }
+
+    #[test]
+    fn parses_amd_log_lines() {
+        let entry = parse_log_line("ERROR: 0:8: 'foo' : undeclared identifier").unwrap();
+        assert_eq!(entry.line, 8);
+        assert_eq!(entry.column, None);
+        assert_eq!(entry.message, "'foo' : undeclared identifier");
+        assert!(!entry.is_warning);
+
+        let entry = parse_log_line("WARNING: 0:2: extension not supported").unwrap();
+        assert_eq!(entry.line, 2);
+        assert!(entry.is_warning);
+    }
+
+    #[test]
+    fn ignores_unrelated_log_lines() {
+        assert!(parse_log_line("").is_none());
+        assert!(parse_log_line("ERROR: 1 compilation errors.  No code generated.").is_none());
+        assert!(parse_log_line("Fragment info").is_none());
+    }
+
+    #[test]
+    fn maps_errors_to_source_files() {
+        let mut fragment_composer = SourceComposer::default();
+        fragment_composer.push(
+            "header.glsl".into(),
+            Box::new(TextShader::new("#version 140\n")),
+        );
+        fragment_composer.push(
+            "main.glsl".into(),
+            Box::new(TextShader::new("void main() {\n    foo;\n}")),
+        );
+
+        let error = ProgramChooserCreationError::ProgramCreationError(
+            ProgramCreationError::CompilationError(
+                "0:3(5): warning: statement has no effect\n0:3(5): error: `foo' undeclared\n"
+                    .to_owned(),
+                ShaderType::Fragment,
+            ),
+        );
+
+        let error_list = ShaderCompileError::from_program_error(
+            &error,
+            "",
+            fragment_composer.get_text(),
+            &SourceMap::default(),
+            fragment_composer.get_source_map(),
+        );
+
+        assert_eq!(error_list.len(), 1);
+        assert_eq!(error_list[0].stage, ShaderStage::Fragment);
+        assert_eq!(error_list[0].file.as_deref(), Some(Path::new("main.glsl")));
+        assert_eq!(error_list[0].line, Some(2));
+        assert_eq!(error_list[0].column, Some(5));
+        assert_eq!(error_list[0].source_line.as_deref(), Some("    foo;"));
+    }
+
+    #[test]
+    fn keeps_unparsed_driver_output() {
+        let error = ProgramChooserCreationError::ProgramCreationError(
+            ProgramCreationError::CompilationError(
+                "  internal compiler failure\n".to_owned(),
+                ShaderType::Vertex,
+            ),
+        );
+
+        let error_list = ShaderCompileError::from_program_error(
+            &error,
+            "",
+            "",
+            &SourceMap::default(),
+            &SourceMap::default(),
+        );
+
+        assert_eq!(error_list.len(), 1);
+        assert_eq!(error_list[0].stage, ShaderStage::Vertex);
+        assert_eq!(error_list[0].line, None);
+        assert_eq!(error_list[0].message, "internal compiler failure");
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use glium::texture::UncompressedFloatFormat;
+
+use crate::{RGBAFloatImageData, RGBAImageData};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExportFormat {
+    /// 8 bits per channel PNG.
+    Png8,
+    /// 16 bits per channel PNG, values are clamped to [0, 1].
+    Png16,
+    /// Portable float map, keeps the full float range but drops alpha.
+    Pfm,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Converts linear values to sRGB before writing, ignored for PFM.
+    pub srgb_encode: bool,
+}
+
+impl ExportOptions {
+    /// Lossless export for the given stage buffer format.
+    pub fn for_buffer_format(buffer_format: UncompressedFloatFormat) -> Self {
+        let format = match buffer_format {
+            UncompressedFloatFormat::U8U8U8U8 => ExportFormat::Png8,
+            _ => ExportFormat::Pfm,
+        };
+
+        Self {
+            format,
+            srgb_encode: false,
+        }
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Writes an 8 bits image read from a texture, whose rows start at the bottom.
+pub fn save_png8(path: &Path, image_data: &RGBAImageData, srgb_encode: bool) -> Result<()> {
+    let file = File::create(path).context("Failed to create image file")?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image_data.width, image_data.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let encode = |value: u8| {
+        if srgb_encode {
+            (linear_to_srgb(value as f32 / 255.0) * 255.0).round() as u8
+        } else {
+            value
+        }
+    };
+
+    let mut pixel_list = Vec::with_capacity(image_data.data.len() * 4);
+    for row in image_data.data.chunks(image_data.width as usize).rev() {
+        for (r, g, b, a) in row {
+            pixel_list.extend_from_slice(&[encode(*r), encode(*g), encode(*b), *a]);
+        }
+    }
+
+    encoder
+        .write_header()
+        .context("Failed to write image header")?
+        .write_image_data(&pixel_list)
+        .context("Failed to write image data")?;
+
+    Ok(())
+}
+
+/// Writes a float image read from a texture, whose rows start at the bottom, as a 16 bits PNG.
+pub fn save_png16(path: &Path, image_data: &RGBAFloatImageData, srgb_encode: bool) -> Result<()> {
+    let file = File::create(path).context("Failed to create image file")?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image_data.width, image_data.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Sixteen);
+
+    let encode = |value: f32, srgb_encode: bool| {
+        let value = if srgb_encode {
+            linear_to_srgb(value)
+        } else {
+            value.clamp(0.0, 1.0)
+        };
+
+        ((value * 65535.0).round() as u16).to_be_bytes()
+    };
+
+    let mut pixel_list = Vec::with_capacity(image_data.data.len() * 8);
+    for row in image_data.data.chunks(image_data.width as usize).rev() {
+        for (r, g, b, a) in row {
+            pixel_list.extend_from_slice(&encode(*r, srgb_encode));
+            pixel_list.extend_from_slice(&encode(*g, srgb_encode));
+            pixel_list.extend_from_slice(&encode(*b, srgb_encode));
+            pixel_list.extend_from_slice(&encode(*a, false));
+        }
+    }
+
+    encoder
+        .write_header()
+        .context("Failed to write image header")?
+        .write_image_data(&pixel_list)
+        .context("Failed to write image data")?;
+
+    Ok(())
+}
+
+/// Writes a float image as a little-endian PFM, which stores rows bottom to top like textures.
+pub fn save_pfm(path: &Path, image_data: &RGBAFloatImageData) -> Result<()> {
+    let file = File::create(path).context("Failed to create image file")?;
+    let mut writer = BufWriter::new(file);
+
+    write!(
+        writer,
+        "PF\n{} {}\n-1.0\n",
+        image_data.width, image_data.height
+    )
+    .context("Failed to write image header")?;
+
+    let mut pixel_list = Vec::with_capacity(image_data.data.len() * 12);
+    for (r, g, b, _) in &image_data.data {
+        pixel_list.extend_from_slice(&r.to_le_bytes());
+        pixel_list.extend_from_slice(&g.to_le_bytes());
+        pixel_list.extend_from_slice(&b.to_le_bytes());
+    }
+
+    writer
+        .write_all(&pixel_list)
+        .context("Failed to write image data")?;
+    writer.flush().context("Failed to write image data")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn writes_pfm_header_and_little_endian_rows() {
+        let path = std::env::temp_dir().join(format!("wvr_export_test_{}.pfm", std::process::id()));
+        let image_data = RGBAFloatImageData {
+            data: vec![(0.0, 0.5, 1.0, 1.0), (-2.0, 4.5, 0.25, 0.0)],
+            width: 2,
+            height: 1,
+        };
+
+        save_pfm(&path, &image_data).unwrap();
+        let file_data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n2 1\n-1.0\n";
+        assert_eq!(&file_data[..header.len()], header);
+
+        let value_list: Vec<f32> = file_data[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(value_list, vec![0.0, 0.5, 1.0, -2.0, 4.5, 0.25]);
+    }
+
+    #[test]
+    fn picks_lossless_format_for_buffers() {
+        assert_eq!(
+            ExportOptions::for_buffer_format(UncompressedFloatFormat::U8U8U8U8).format,
+            ExportFormat::Png8
+        );
+        assert_eq!(
+            ExportOptions::for_buffer_format(UncompressedFloatFormat::F16F16F16F16).format,
+            ExportFormat::Pfm
+        );
+    }
+
+    #[test]
+    fn encodes_linear_values_to_srgb() {
+        assert_eq!(linear_to_srgb(-1.0), 0.0);
+        assert!((linear_to_srgb(0.18) - 0.461).abs() < 0.001);
+        assert!((linear_to_srgb(2.0) - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,554 @@
+use std::convert::TryInto;
+
+use anyhow::{Context, Result};
+
+use glium::backend::Facade;
+use glium::texture::RawImage2d;
+use glium::texture::Texture2d;
+use glium::Program;
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+const MAX_COLUMN_COUNT: usize = 96;
+const MAX_LINE_COUNT: usize = 48;
+
+pub const ERROR_TEXT_UNIFORM_NAME: &str = "wvr_error_text";
+pub const ERROR_TEXT_SIZE_UNIFORM_NAME: &str = "wvr_error_text_size";
+
+const FALLBACK_VERTEX_SHADER: &str = r#"
+#version 140
+
+in vec2 position;
+in vec2 tex_coords;
+
+out vec2 uv;
+
+void main() {
+    uv = tex_coords;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"#;
+
+const FALLBACK_FRAGMENT_SHADER: &str = r#"
+#version 140
+
+uniform vec3 iResolution;
+uniform sampler2D wvr_error_text;
+uniform vec2 wvr_error_text_size;
+
+in vec2 uv;
+
+out vec4 color;
+
+void main() {
+    vec2 pixel = uv * iResolution.xy;
+
+    vec2 cell = floor(pixel / 32.0);
+    float checker = mod(cell.x + cell.y, 2.0);
+    vec3 background = mix(vec3(1.0, 0.0, 1.0), vec3(0.25, 0.0, 0.25), checker);
+
+    float text_scale = max(1.0, floor(iResolution.x / 640.0) + 1.0);
+    vec2 text_pixel = vec2(pixel.x, iResolution.y - pixel.y) - vec2(16.0);
+    vec2 text_uv = text_pixel / (wvr_error_text_size * text_scale);
+
+    if (all(greaterThanEqual(text_uv, vec2(0.0))) && all(lessThan(text_uv, vec2(1.0)))) {
+        float text = texture(wvr_error_text, text_uv).r;
+        background = mix(background * 0.2, vec3(1.0), text);
+    }
+
+    color = vec4(background, 1.0);
+}
+"#;
+
+const GLYPH_LIST: [(char, [u8; GLYPH_HEIGHT]); 68] = [
+    (
+        'A',
+        [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'B',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+    ),
+    (
+        'C',
+        [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'D',
+        [
+            0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+        ],
+    ),
+    (
+        'E',
+        [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        'F',
+        [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+    ),
+    (
+        'G',
+        [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+    ),
+    (
+        'H',
+        [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'I',
+        [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+    ),
+    (
+        'J',
+        [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+    ),
+    (
+        'K',
+        [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+    ),
+    (
+        'L',
+        [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        'M',
+        [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'N',
+        [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'O',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'P',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+    ),
+    (
+        'Q',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+    ),
+    (
+        'R',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+    ),
+    (
+        'S',
+        [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+    ),
+    (
+        'T',
+        [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        'U',
+        [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'V',
+        [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+    ),
+    (
+        'W',
+        [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+    ),
+    (
+        'X',
+        [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'Y',
+        [
+            0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        'Z',
+        [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        '0',
+        [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '1',
+        [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+    ),
+    (
+        '2',
+        [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+    ),
+    (
+        '3',
+        [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '4',
+        [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+    ),
+    (
+        '5',
+        [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '6',
+        [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '7',
+        [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+    ),
+    (
+        '8',
+        [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '9',
+        [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+    ),
+    (
+        '!',
+        [
+            0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100,
+        ],
+    ),
+    (
+        '"',
+        [
+            0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '#',
+        [
+            0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+        ],
+    ),
+    (
+        '$',
+        [
+            0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100,
+        ],
+    ),
+    (
+        '%',
+        [
+            0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+        ],
+    ),
+    (
+        '&',
+        [
+            0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101,
+        ],
+    ),
+    (
+        '\'',
+        [
+            0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '(',
+        [
+            0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+        ],
+    ),
+    (
+        ')',
+        [
+            0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '*',
+        [
+            0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000,
+        ],
+    ),
+    (
+        '+',
+        [
+            0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+        ],
+    ),
+    (
+        ',',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '-',
+        [
+            0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '.',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+        ],
+    ),
+    (
+        '/',
+        [
+            0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+        ],
+    ),
+    (
+        ':',
+        [
+            0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+        ],
+    ),
+    (
+        ';',
+        [
+            0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '<',
+        [
+            0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010,
+        ],
+    ),
+    (
+        '=',
+        [
+            0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '>',
+        [
+            0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '?',
+        [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+        ],
+    ),
+    (
+        '@',
+        [
+            0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110,
+        ],
+    ),
+    (
+        '[',
+        [
+            0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110,
+        ],
+    ),
+    (
+        '\\',
+        [
+            0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000,
+        ],
+    ),
+    (
+        ']',
+        [
+            0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110,
+        ],
+    ),
+    (
+        '^',
+        [
+            0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '_',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+        ],
+    ),
+    (
+        '`',
+        [
+            0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '{',
+        [
+            0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010,
+        ],
+    ),
+    (
+        '|',
+        [
+            0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        '}',
+        [
+            0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '~',
+        [
+            0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000,
+        ],
+    ),
+];
+
+pub fn build_fallback_program(display: &dyn Facade) -> Result<Program> {
+    program!(display, 140 => { vertex: FALLBACK_VERTEX_SHADER, fragment: FALLBACK_FRAGMENT_SHADER, outputs_srgb: true })
+        .context("Failed to build fallback shader program")
+}
+
+/// Rasterises `text` with a built-in 5x7 bitmap font, first line at the top of the texture.
+pub fn build_error_text_texture(
+    display: &dyn Facade,
+    text: &str,
+) -> Result<(Texture2d, (u32, u32))> {
+    let mut line_list = Vec::new();
+    for line in text.lines() {
+        let char_list: Vec<char> = line.replace('\t', "    ").chars().collect();
+        if char_list.is_empty() {
+            line_list.push(Vec::new());
+        }
+        for chunk in char_list.chunks(MAX_COLUMN_COUNT) {
+            line_list.push(chunk.to_vec());
+        }
+    }
+    line_list.truncate(MAX_LINE_COUNT);
+
+    let column_count = line_list.iter().map(Vec::len).max().unwrap_or(0).max(1);
+    let line_count = line_list.len().max(1);
+
+    let width = column_count * CELL_WIDTH;
+    let height = line_count * CELL_HEIGHT;
+    let mut pixel_list = vec![0u8; width * height * 3];
+
+    for (line_index, line) in line_list.iter().enumerate() {
+        for (column_index, character) in line.iter().enumerate() {
+            let glyph = match find_glyph(*character) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+
+            for (glyph_y, glyph_row) in glyph.iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if glyph_row & (1 << (GLYPH_WIDTH - 1 - glyph_x)) == 0 {
+                        continue;
+                    }
+
+                    let x = column_index * CELL_WIDTH + glyph_x;
+                    let y = line_index * CELL_HEIGHT + glyph_y;
+                    let offset = (y * width + x) * 3;
+                    pixel_list[offset..offset + 3].copy_from_slice(&[255, 255, 255]);
+                }
+            }
+        }
+    }
+
+    let resolution = (
+        width.try_into().context("Error text is too wide")?,
+        height.try_into().context("Error text is too tall")?,
+    );
+    let image = RawImage2d::from_raw_rgb(pixel_list, resolution);
+    let texture =
+        Texture2d::new(display, image).context("Failed to build texture for error text")?;
+
+    Ok((texture, resolution))
+}
+
+fn find_glyph(character: char) -> Option<&'static [u8; GLYPH_HEIGHT]> {
+    if character == ' ' {
+        return None;
+    }
+
+    let character = character.to_ascii_uppercase();
+    GLYPH_LIST
+        .iter()
+        .find(|(glyph_character, _)| *glyph_character == character)
+        .or_else(|| {
+            GLYPH_LIST
+                .iter()
+                .find(|(glyph_character, _)| *glyph_character == '?')
+        })
+        .map(|(_, glyph)| glyph)
+}
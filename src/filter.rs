@@ -1,38 +1,203 @@
+use std::cell::RefCell;
 use std::convert::TryFrom;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{collections::HashMap, path::MAIN_SEPARATOR};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Error, Result};
 
 use glium::backend::Facade;
-use glium::framebuffer::SimpleFrameBuffer;
+use glium::draw_parameters::TimeElapsedQuery;
+use glium::framebuffer::{DepthRenderBuffer, MultiOutputFrameBuffer, SimpleFrameBuffer};
 use glium::index::PrimitiveType;
-use glium::program::ProgramChooserCreationError;
-use glium::program::ProgramCreationError;
-use glium::program::ShaderType;
 use glium::texture::texture2d::Texture2d;
 use glium::texture::DepthTexture2d;
 use glium::texture::SrgbTexture2d;
+use glium::texture::UncompressedFloatFormat;
+use glium::texture::{IntegralTexture2d, UnsignedTexture2d};
 use glium::uniforms::{AsUniformValue, UniformValue, Uniforms};
 use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
 use glium::uniforms::{Sampler, SamplerWrapFunction};
+use glium::DrawError;
 use glium::Frame;
 use glium::IndexBuffer;
 use glium::Program;
 use glium::Surface;
 use glium::VertexBuffer;
+use glium::{Blend, BlendingFunction, Depth, DepthTest, LinearBlendingFactor};
 
 use wvr_data::config::filter::{FilterConfig, FilterMode};
+use wvr_data::shader::FileShader;
 use wvr_data::shader::Shader;
-use wvr_data::shader::{FileShader, ShaderComposer};
 
-use crate::uniform::UniformHolder;
+use crate::error::{ProgramCompileError, ShaderCompileError};
+use crate::fallback::{build_error_text_texture, build_fallback_program};
+use crate::fallback::{ERROR_TEXT_SIZE_UNIFORM_NAME, ERROR_TEXT_UNIFORM_NAME};
+use crate::libs::get_lib_shader;
+use crate::source_map::SourceComposer;
+use crate::uniform::{get_buffer_layout, BufferTexture, UniformHolder};
+
+/// Sampling settings of an input on top of the filtering given by its `InputSampler`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SamplerOptions {
+    /// `None` repeats textures and stage buffers and clamps arrays to their border.
+    pub wrap_function: Option<SamplerWrapFunction>,
+    /// Maximum anisotropy, 1 disables anisotropic filtering and lower values are raised to 1.
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            wrap_function: None,
+            anisotropy: 1,
+        }
+    }
+}
+
+/// How a stage output is combined with the content of its target.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlendMode {
+    /// Overwrites the target.
+    Replace,
+    /// Blends outputs whose color is not multiplied by their alpha.
+    Alpha,
+    /// Blends outputs whose color is already multiplied by their alpha.
+    PremultipliedAlpha,
+    Additive,
+    Multiply,
+    Screen,
+    Min,
+    Max,
+    Custom(Blend),
+}
+
+impl BlendMode {
+    pub fn to_blend(self) -> Blend {
+        let addition = |source, destination| BlendingFunction::Addition {
+            source,
+            destination,
+        };
+
+        let (color, alpha) = match self {
+            BlendMode::Replace => return Blend::default(),
+            BlendMode::Alpha => (
+                addition(
+                    LinearBlendingFactor::SourceAlpha,
+                    LinearBlendingFactor::OneMinusSourceAlpha,
+                ),
+                addition(
+                    LinearBlendingFactor::One,
+                    LinearBlendingFactor::OneMinusSourceAlpha,
+                ),
+            ),
+            BlendMode::PremultipliedAlpha => (
+                addition(
+                    LinearBlendingFactor::One,
+                    LinearBlendingFactor::OneMinusSourceAlpha,
+                ),
+                addition(
+                    LinearBlendingFactor::One,
+                    LinearBlendingFactor::OneMinusSourceAlpha,
+                ),
+            ),
+            BlendMode::Additive => (
+                addition(LinearBlendingFactor::One, LinearBlendingFactor::One),
+                addition(LinearBlendingFactor::One, LinearBlendingFactor::One),
+            ),
+            BlendMode::Multiply => (
+                addition(
+                    LinearBlendingFactor::DestinationColor,
+                    LinearBlendingFactor::Zero,
+                ),
+                addition(
+                    LinearBlendingFactor::DestinationAlpha,
+                    LinearBlendingFactor::Zero,
+                ),
+            ),
+            BlendMode::Screen => (
+                addition(
+                    LinearBlendingFactor::One,
+                    LinearBlendingFactor::OneMinusSourceColor,
+                ),
+                addition(
+                    LinearBlendingFactor::One,
+                    LinearBlendingFactor::OneMinusSourceAlpha,
+                ),
+            ),
+            BlendMode::Min => (BlendingFunction::Min, BlendingFunction::Min),
+            BlendMode::Max => (BlendingFunction::Max, BlendingFunction::Max),
+            BlendMode::Custom(blend) => return blend,
+        };
+
+        Blend {
+            color,
+            alpha,
+            constant_value: (1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClearMode {
+    /// Transparent black for stage buffers, opaque black for the window and offscreen buffer.
+    Default,
+    Color((f32, f32, f32, f32)),
+    /// Draws over the previous content of the target.
+    Keep,
+    /// Draws over the previous frame of the stage, faded by the given factor first.
+    /// Behaves like `Keep` for the final stage, whose target cannot be faded in place.
+    Accumulate(f32),
+}
+
+/// Per-stage state applied when drawing a filter into its target.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrawOptions {
+    /// `None` replaces the target, or adds to it for particle filters.
+    pub blend_mode: Option<BlendMode>,
+    pub clear_mode: ClearMode,
+    /// Comparison used against a depth buffer cleared every frame, `None` disables depth testing.
+    pub depth_test: Option<DepthTest>,
+    pub depth_write: bool,
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        Self {
+            blend_mode: None,
+            clear_mode: ClearMode::Default,
+            depth_test: None,
+            depth_write: true,
+        }
+    }
+}
+
+/// Filtering and sampler options of an input, `None` for values that are not sampled.
+pub type InputSampling = Option<(MinifySamplerFilter, MagnifySamplerFilter, SamplerOptions)>;
 
 pub enum RenderTarget<'a> {
     FrameBuffer(&'a Texture2d),
+    /// One texture per named fragment output.
+    MultiFrameBuffer(Vec<(&'a String, &'a Texture2d)>),
+    /// Stands in for the window when rendering without one.
+    Offscreen(&'a Texture2d),
     Window(&'a mut Frame),
 }
 
+impl RenderTarget<'_> {
+    /// Size of the target textures, `None` for the window.
+    pub fn get_dimensions(&self) -> Option<(u32, u32)> {
+        match self {
+            RenderTarget::FrameBuffer(texture) | RenderTarget::Offscreen(texture) => {
+                Some(texture.dimensions())
+            }
+            RenderTarget::MultiFrameBuffer(texture_list) => texture_list
+                .first()
+                .map(|(_, texture)| texture.dimensions()),
+            RenderTarget::Window(_) => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Vertex {
     position: [f32; 2],
@@ -48,12 +213,197 @@ pub struct InstanceAttributes {
 
 implement_vertex!(InstanceAttributes, instance_id);
 
+/// Full screen quad drawn as a triangle strip.
+pub fn build_quad_buffers(
+    display: &dyn Facade,
+) -> Result<(VertexBuffer<Vertex>, IndexBuffer<u16>)> {
+    let vertex_buffer = {
+        VertexBuffer::new(
+            display,
+            &[
+                Vertex {
+                    position: [-1.0, -1.0],
+                    tex_coords: [0.0, 0.0],
+                },
+                Vertex {
+                    position: [-1.0, 1.0],
+                    tex_coords: [0.0, 1.0],
+                },
+                Vertex {
+                    position: [1.0, 1.0],
+                    tex_coords: [1.0, 1.0],
+                },
+                Vertex {
+                    position: [1.0, -1.0],
+                    tex_coords: [1.0, 0.0],
+                },
+            ],
+        )
+        .context("Failed to create vertex buffer")?
+    };
+
+    // building the index buffer
+    let index_buffer = IndexBuffer::new(display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3])
+        .context("Failed to create index buffer")?;
+
+    Ok((vertex_buffer, index_buffer))
+}
+
+#[derive(Default)]
 struct CustomUniforms<'hihi> {
     pub primitive_list: Vec<(&'hihi String, &'hihi dyn AsUniformValue)>,
     pub render_targets_list: Vec<(&'hihi String, Sampler<'hihi, Texture2d>)>,
     pub texture_list: Vec<(&'hihi String, Sampler<'hihi, Texture2d>)>,
     pub srgb_texture_list: Vec<(&'hihi String, Sampler<'hihi, SrgbTexture2d>)>,
     pub buffer_list: Vec<(&'hihi String, Sampler<'hihi, DepthTexture2d>)>,
+    pub integer_buffer_list: Vec<(&'hihi String, Sampler<'hihi, IntegralTexture2d>)>,
+    pub unsigned_buffer_list: Vec<(&'hihi String, Sampler<'hihi, UnsignedTexture2d>)>,
+    pub buffer_layout_list: Vec<(String, (i32, i32, i32))>,
+}
+
+/// Samples `texture` with the filtering and options of an input, `default_wrap_function`
+/// applies when the input does not set a wrap mode.
+fn build_sampler<T>(
+    texture: &T,
+    sampling: (MinifySamplerFilter, MagnifySamplerFilter, SamplerOptions),
+    default_wrap_function: SamplerWrapFunction,
+) -> Sampler<'_, T> {
+    let (down_sampling, up_sampling, sampler_options) = sampling;
+
+    Sampler::new(texture)
+        .wrap_function(
+            sampler_options
+                .wrap_function
+                .unwrap_or(default_wrap_function),
+        )
+        .minify_filter(down_sampling)
+        .magnify_filter(up_sampling)
+        .anisotropy(sampler_options.anisotropy.max(1))
+}
+
+impl<'hihi> CustomUniforms<'hihi> {
+    /// Adds a value to the uniforms, textures and buffers are skipped when not sampled.
+    fn bind_uniform(
+        &mut self,
+        uniform_name: &'hihi String,
+        value: &'hihi UniformHolder,
+        sampling: InputSampling,
+    ) {
+        match value {
+            UniformHolder::Buffer((texture, length)) => {
+                if let Some(sampling) = sampling {
+                    // Integer textures cannot be filtered
+                    let unfiltered_sampling = (
+                        MinifySamplerFilter::Nearest,
+                        MagnifySamplerFilter::Nearest,
+                        SamplerOptions {
+                            anisotropy: 1,
+                            ..sampling.2
+                        },
+                    );
+
+                    match texture {
+                        BufferTexture::Float(texture) => self.buffer_list.push((
+                            uniform_name,
+                            build_sampler(texture, sampling, SamplerWrapFunction::BorderClamp),
+                        )),
+                        BufferTexture::Integer(texture) => self.integer_buffer_list.push((
+                            uniform_name,
+                            build_sampler(
+                                texture,
+                                unfiltered_sampling,
+                                SamplerWrapFunction::BorderClamp,
+                            ),
+                        )),
+                        BufferTexture::Unsigned(texture) => self.unsigned_buffer_list.push((
+                            uniform_name,
+                            build_sampler(
+                                texture,
+                                unfiltered_sampling,
+                                SamplerWrapFunction::BorderClamp,
+                            ),
+                        )),
+                    }
+
+                    self.buffer_layout_list.push((
+                        format!("{}_layout", uniform_name),
+                        get_buffer_layout(texture.dimensions(), *length),
+                    ));
+                }
+            }
+            UniformHolder::VectorBuffer((texture, length)) => {
+                if let Some(sampling) = sampling {
+                    self.texture_list.push((
+                        uniform_name,
+                        build_sampler(texture, sampling, SamplerWrapFunction::BorderClamp),
+                    ));
+                    self.buffer_layout_list.push((
+                        format!("{}_layout", uniform_name),
+                        get_buffer_layout(texture.dimensions(), *length),
+                    ));
+                }
+            }
+            UniformHolder::Texture((texture, _resolution, _format)) => {
+                if let Some(sampling) = sampling {
+                    self.texture_list.push((
+                        uniform_name,
+                        build_sampler(texture, sampling, SamplerWrapFunction::Repeat),
+                    ));
+                }
+            }
+            UniformHolder::SrgbTexture((texture, _resolution, _format)) => {
+                if let Some(sampling) = sampling {
+                    self.srgb_texture_list.push((
+                        uniform_name,
+                        build_sampler(texture, sampling, SamplerWrapFunction::Repeat),
+                    ));
+                }
+            }
+            UniformHolder::Float(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Float2(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Float3(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Float4(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Integer(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Integer2(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Integer3(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Integer4(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Bool(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Mat2(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Mat3(value) => self.primitive_list.push((uniform_name, value)),
+            UniformHolder::Mat4(value) => self.primitive_list.push((uniform_name, value)),
+        }
+    }
+}
+
+/// Names the sampler type expected for integer, boolean and byte arrays, which shaders
+/// declaring them as `sampler2D` do not match.
+fn describe_draw_error(error: DrawError, uniforms: &CustomUniforms) -> Error {
+    if let DrawError::UniformTypeMismatch { name, expected } = &error {
+        let sampler_type = if uniforms
+            .integer_buffer_list
+            .iter()
+            .any(|(uniform_name, _)| *uniform_name == name)
+        {
+            Some("isampler2D")
+        } else if uniforms
+            .unsigned_buffer_list
+            .iter()
+            .any(|(uniform_name, _)| *uniform_name == name)
+        {
+            Some("usampler2D")
+        } else {
+            None
+        };
+
+        if let Some(sampler_type) = sampler_type {
+            return Error::msg(format!(
+                "Uniform {} must be declared as {} to read its array, the shader declares {:?}",
+                name, sampler_type, expected
+            ));
+        }
+    }
+
+    Error::from(error)
 }
 
 impl<'hihi> Uniforms for CustomUniforms<'hihi> {
@@ -77,84 +427,105 @@ impl<'hihi> Uniforms for CustomUniforms<'hihi> {
         for (uniform_name, buffer_sampler) in self.buffer_list.iter() {
             output(uniform_name, buffer_sampler.as_uniform_value());
         }
+
+        for (uniform_name, buffer_sampler) in self.integer_buffer_list.iter() {
+            output(uniform_name, buffer_sampler.as_uniform_value());
+        }
+
+        for (uniform_name, buffer_sampler) in self.unsigned_buffer_list.iter() {
+            output(uniform_name, buffer_sampler.as_uniform_value());
+        }
+
+        for (uniform_name, buffer_layout) in self.buffer_layout_list.iter() {
+            output(uniform_name, buffer_layout.as_uniform_value());
+        }
     }
 }
 
-fn parse_error_message(
-    error: &ProgramChooserCreationError,
-    vertex_text: &str,
-    fragment_text: &str,
-) -> Result<String> {
-    let mut result_message = String::new();
-    match error {
-        ProgramChooserCreationError::ProgramCreationError(e) => {
-            match e {
-                ProgramCreationError::CompilationError(message, shader_type) => {
-                    let mut message_parts = message.split(':');
-                    if message_parts.next().is_some() {
-                        if let Some(position_info) = message_parts.next() {
-                            let mut position_info_parts = position_info.split('(');
-                            if let Some(error_line) = position_info_parts.next() {
-                                let error_line: usize = error_line
-                                    .parse()
-                                    .context("Failed to parse error line for shader error.")?;
-                                if let Some(error_char) = position_info_parts.next() {
-                                    let error_char: usize =
-                                        error_char[..error_char.len() - 1].parse().context(
-                                            "Failed to parse error position for shader error",
-                                        )?;
-                                    let error_message = message_parts
-                                        .collect::<String>()
-                                        .lines()
-                                        .next()
-                                        .unwrap_or("")
-                                        .to_owned();
-
-                                    let code_line = match shader_type {
-                                    ShaderType::Vertex => vertex_text.lines().nth(error_line - 1).context("Failed to find faulty error in vertex shader file")?,
-                                    ShaderType::Fragment => fragment_text.lines().nth(error_line - 1).context("Failed to find faulty error in fragment shader file")?,
-                                    _ => unreachable!(),
-                                };
-
-                                    result_message.push_str(&code_line.to_string());
-                                    result_message.push('\n');
-
-                                    result_message.push_str(
-                                        &(0..error_char).map(|_| " ").collect::<String>(),
-                                    );
-                                    result_message.push('^');
-                                    result_message.push('\n');
-
-                                    result_message.push_str(&error_message);
-                                    result_message.push('\n');
-                                }
-                            }
-                        }
+/// Lists the `out` variables declared at the top level of a fragment shader, in order.
+fn parse_fragment_outputs(fragment_text: &str) -> Vec<String> {
+    let mut output_list = Vec::new();
+    let mut scope_depth = 0usize;
+
+    for line in fragment_text.lines() {
+        let code = line.split("//").next().unwrap_or("");
+
+        if scope_depth == 0 {
+            let mut declaration = code.trim();
+            if declaration.starts_with("layout") {
+                if let Some((_, rest)) = declaration.split_once(')') {
+                    declaration = rest.trim();
+                }
+            }
+
+            let mut token_list = declaration
+                .split_whitespace()
+                .filter(|token| !matches!(*token, "highp" | "mediump" | "lowp"));
+            if token_list.next() == Some("out") {
+                if let Some(output_name) = token_list.nth(1) {
+                    let output_name = output_name.split([';', '[']).next().unwrap_or("");
+                    if !output_name.is_empty()
+                        && output_name
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    {
+                        output_list.push(output_name.to_owned());
                     }
                 }
-                e => result_message.push_str(&e.to_string()),
             }
         }
-        e => result_message.push_str(&e.to_string()),
+
+        scope_depth += code.matches('{').count();
+        scope_depth = scope_depth.saturating_sub(code.matches('}').count());
     }
 
-    Ok(result_message)
+    output_list
+}
+
+#[derive(Debug, Clone)]
+pub enum FilterStatus {
+    Compiled,
+    /// The last reload failed, the previously compiled program is still in use.
+    Stale(Vec<ShaderCompileError>),
+    /// The filter never compiled, the fallback error program is in use.
+    Fallback(Vec<ShaderCompileError>),
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone)]
+pub enum FilterEvent {
+    FilterReloaded,
+    FilterCompileFailed {
+        diagnostics: Vec<ShaderCompileError>,
+    },
+    FilterSourceMissing {
+        message: String,
+    },
+    /// The filter could not be built at all and was left out of the view.
+    FilterLoadFailed {
+        message: String,
+    },
 }
 
 pub struct Filter {
     mode: FilterMode,
 
     inputs: Vec<String>,
+    output_list: Vec<String>,
 
-    vertex_shader: Box<dyn Shader>,
-    fragment_shader: Box<dyn Shader>,
+    vertex_shader: SourceComposer,
+    fragment_shader: SourceComposer,
 
     vertex_buffer: VertexBuffer<Vertex>,
     index_buffer: IndexBuffer<u16>,
+    /// Instance ids kept across renders, grown to the largest particle count drawn so far.
+    instance_attribute_buffer: RefCell<Option<VertexBuffer<InstanceAttributes>>>,
 
     vertex_text: String,
     fragment_text: String,
     program: Program,
+    status: FilterStatus,
+    source_missing: bool,
 
     resolution: (usize, usize),
     time: f64,
@@ -162,13 +533,7 @@ pub struct Filter {
     frame_count: usize,
     mouse_position: (f64, f64, f64, f64),
 
-    uniform_holder: HashMap<
-        String,
-        (
-            UniformHolder,
-            Option<(MinifySamplerFilter, MagnifySamplerFilter)>,
-        ),
-    >,
+    uniform_holder: HashMap<String, (UniformHolder, InputSampling)>,
 }
 
 impl Filter {
@@ -179,7 +544,7 @@ impl Filter {
         resolution: (usize, usize),
         system_filter: bool,
     ) -> Result<Self> {
-        let mut vertex_shader = Box::new(ShaderComposer::default());
+        let mut vertex_shader = SourceComposer::default();
 
         for shader_file in config.vertex_shader.iter() {
             let shader_file = shader_file.replace('/', MAIN_SEPARATOR.to_string().as_str());
@@ -193,19 +558,25 @@ impl Filter {
                 }
             }
             if shader_file_path.is_none() {
+                if let Some(lib_shader) = get_lib_shader(&shader_file) {
+                    vertex_shader.push(PathBuf::from(&shader_file), lib_shader);
+                    continue;
+                }
+
                 return std::result::Result::Err(anyhow::Error::new(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
                     format!("Can't find source file {:?}", &shader_file),
                 )));
             }
 
-            vertex_shader.push(Box::new(FileShader::new(
-                shader_file_path.unwrap(),
-                !system_filter,
-            )?));
+            let shader_file_path = shader_file_path.unwrap();
+            vertex_shader.push(
+                shader_file_path.clone(),
+                Box::new(FileShader::new(shader_file_path, !system_filter)?),
+            );
         }
 
-        let mut fragment_shader = Box::new(ShaderComposer::default());
+        let mut fragment_shader = SourceComposer::default();
 
         for shader_file in config.fragment_shader.iter() {
             let shader_file = shader_file.replace('/', MAIN_SEPARATOR.to_string().as_str());
@@ -219,16 +590,22 @@ impl Filter {
                 }
             }
             if shader_file_path.is_none() {
+                if let Some(lib_shader) = get_lib_shader(&shader_file) {
+                    fragment_shader.push(PathBuf::from(&shader_file), lib_shader);
+                    continue;
+                }
+
                 return std::result::Result::Err(anyhow::Error::new(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
                     format!("Can't find source file {:?}", &shader_file),
                 )));
             }
 
-            fragment_shader.push(Box::new(FileShader::new(
-                shader_file_path.unwrap(),
-                !system_filter,
-            )?));
+            let shader_file_path = shader_file_path.unwrap();
+            fragment_shader.push(
+                shader_file_path.clone(),
+                Box::new(FileShader::new(shader_file_path, !system_filter)?),
+            );
         }
 
         let mut uniform_holder = HashMap::new();
@@ -251,79 +628,56 @@ impl Filter {
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         display: &dyn Facade,
         resolution: (usize, usize),
         mode: FilterMode,
-        vertex_shader: Box<dyn Shader>,
-        fragment_shader: Box<dyn Shader>,
+        vertex_shader: SourceComposer,
+        fragment_shader: SourceComposer,
         inputs: Vec<String>,
-        uniform_holder: HashMap<
-            String,
-            (
-                UniformHolder,
-                Option<(MinifySamplerFilter, MagnifySamplerFilter)>,
-            ),
-        >,
+        uniform_holder: HashMap<String, (UniformHolder, InputSampling)>,
     ) -> Result<Self> {
-        let vertex_buffer = {
-            VertexBuffer::new(
-                display,
-                &[
-                    Vertex {
-                        position: [-1.0, -1.0],
-                        tex_coords: [0.0, 0.0],
-                    },
-                    Vertex {
-                        position: [-1.0, 1.0],
-                        tex_coords: [0.0, 1.0],
-                    },
-                    Vertex {
-                        position: [1.0, 1.0],
-                        tex_coords: [1.0, 1.0],
-                    },
-                    Vertex {
-                        position: [1.0, -1.0],
-                        tex_coords: [1.0, 0.0],
-                    },
-                ],
-            )
-            .context("Failed to create vertex buffer")?
-        };
-
-        // building the index buffer
-        let index_buffer =
-            IndexBuffer::new(display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3])
-                .context("Failed to create index buffer")?;
+        let (vertex_buffer, index_buffer) = build_quad_buffers(display)?;
 
         let vertex_text = vertex_shader.get_text().to_owned();
         let fragment_text = fragment_shader.get_text().to_owned();
 
         // compiling shaders and linking them together
 
-        let program = match program!(display, 140 => { vertex: &vertex_text, fragment: &fragment_text, outputs_srgb: true })
+        let (program, status) = match program!(display, 140 => { vertex: &vertex_text, fragment: &fragment_text, outputs_srgb: true })
         {
-            Ok(program) => program,
-            Err(e) => panic!(
-                "{:}",
-                parse_error_message(&e, &vertex_text, &fragment_text)
-                    .unwrap_or(format!("Unexpected shader error: {:?}", e))
+            Ok(program) => (program, FilterStatus::Compiled),
+            Err(e) => (
+                build_fallback_program(display)?,
+                FilterStatus::Fallback(ShaderCompileError::from_program_error(
+                    &e,
+                    &vertex_text,
+                    &fragment_text,
+                    vertex_shader.get_source_map(),
+                    fragment_shader.get_source_map(),
+                )),
             ),
         };
-        Ok(Self {
+
+        let mut filter = Self {
             mode,
 
             inputs,
+            output_list: parse_fragment_outputs(&fragment_text),
 
             vertex_shader,
             fragment_shader,
 
             vertex_buffer,
             index_buffer,
+            instance_attribute_buffer: RefCell::new(None),
 
             vertex_text,
             fragment_text,
             program,
+            status,
+            source_missing: false,
 
             resolution,
             time: 0.0,
@@ -332,7 +686,57 @@ impl Filter {
             frame_count: 0,
 
             uniform_holder,
-        })
+        };
+
+        if let FilterStatus::Fallback(diagnostics) = &filter.status {
+            let error_message = ProgramCompileError {
+                diagnostics: diagnostics.clone(),
+            }
+            .to_string();
+
+            filter.set_error_text(display, &error_message)?;
+        }
+
+        Ok(filter)
+    }
+
+    /// Fragment outputs declared by the filter, each one gets its own buffer in a stage.
+    pub fn get_output_list(&self) -> &[String] {
+        &self.output_list
+    }
+
+    pub fn get_status(&self) -> &FilterStatus {
+        &self.status
+    }
+
+    fn set_error_text(&mut self, display: &dyn Facade, error_message: &str) -> Result<()> {
+        let (texture, resolution) = build_error_text_texture(display, error_message)?;
+
+        self.uniform_holder.insert(
+            ERROR_TEXT_UNIFORM_NAME.to_owned(),
+            (
+                UniformHolder::Texture((texture, resolution, UncompressedFloatFormat::U8U8U8)),
+                Some((
+                    MinifySamplerFilter::Nearest,
+                    MagnifySamplerFilter::Nearest,
+                    SamplerOptions::default(),
+                )),
+            ),
+        );
+        self.uniform_holder.insert(
+            ERROR_TEXT_SIZE_UNIFORM_NAME.to_owned(),
+            (
+                UniformHolder::Float2((resolution.0 as f32, resolution.1 as f32)),
+                None,
+            ),
+        );
+
+        Ok(())
+    }
+
+    fn clear_error_text(&mut self) {
+        self.uniform_holder.remove(ERROR_TEXT_UNIFORM_NAME);
+        self.uniform_holder.remove(ERROR_TEXT_SIZE_UNIFORM_NAME);
     }
 
     pub fn set_time(&mut self, time: f64) {
@@ -355,14 +759,19 @@ impl Filter {
         self.mouse_position = (position.0, position.1, 0.0, 0.0);
     }
 
-    pub fn update(&mut self, display: &dyn Facade) {
+    pub fn update(&mut self, display: &dyn Facade) -> Vec<FilterEvent> {
+        let mut event_list = Vec::new();
+
         self.vertex_shader.update();
         self.fragment_shader.update();
 
+        let mut source_error_list = Vec::new();
+
         let vertex_changed = match self.vertex_shader.check_changes() {
             Ok(changed) => changed,
             Err(e) => {
                 eprintln!("{:?}", e);
+                source_error_list.push(format!("{:?}", e));
                 false
             }
         };
@@ -371,10 +780,20 @@ impl Filter {
             Ok(changed) => changed,
             Err(e) => {
                 eprintln!("{:?}", e);
+                source_error_list.push(format!("{:?}", e));
                 false
             }
         };
 
+        if source_error_list.is_empty() {
+            self.source_missing = false;
+        } else if !self.source_missing {
+            self.source_missing = true;
+            event_list.push(FilterEvent::FilterSourceMissing {
+                message: source_error_list.join("\n"),
+            });
+        }
+
         if vertex_changed || fragment_changed {
             if vertex_changed {
                 self.vertex_text.clear();
@@ -390,12 +809,38 @@ impl Filter {
             {
                 Ok(new_program) => {
                     self.program = new_program;
+                    self.output_list = parse_fragment_outputs(&self.fragment_text);
+                    self.status = FilterStatus::Compiled;
+                    self.clear_error_text();
+
+                    event_list.push(FilterEvent::FilterReloaded);
+                }
+                Err(e) => {
+                    let diagnostics = ShaderCompileError::from_program_error(
+                        &e,
+                        &self.vertex_text,
+                        &self.fragment_text,
+                        self.vertex_shader.get_source_map(),
+                        self.fragment_shader.get_source_map(),
+                    );
+                    let error_message = ProgramCompileError {
+                        diagnostics: diagnostics.clone(),
+                    }
+                    .to_string();
+
+                    eprintln!("{:}", error_message);
+
+                    if let FilterStatus::Fallback(_) = self.status {
+                        if let Err(e) = self.set_error_text(display, &error_message) {
+                            eprintln!("{:?}", e);
+                        }
+                        self.status = FilterStatus::Fallback(diagnostics.clone());
+                    } else {
+                        self.status = FilterStatus::Stale(diagnostics.clone());
+                    }
+
+                    event_list.push(FilterEvent::FilterCompileFailed { diagnostics });
                 }
-                Err(e) => eprintln!(
-                    "{:}",
-                    parse_error_message(&e, &self.vertex_text, &self.fragment_text)
-                        .unwrap_or(format!("Unexpected shader error: {:?}", e))
-                ),
             }
         }
 
@@ -443,112 +888,89 @@ impl Filter {
             "iFrame".to_owned(),
             (UniformHolder::Integer(self.frame_count as i32), None),
         );
+
+        event_list
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &self,
         display: &dyn Facade,
-        input_uniform_holder: &HashMap<
-            &String,
-            (
-                &UniformHolder,
-                Option<(MinifySamplerFilter, MagnifySamplerFilter)>,
-            ),
-        >,
-        render_buffers: &HashMap<
-            &String,
-            (
-                &Texture2d,
-                Option<(MinifySamplerFilter, MagnifySamplerFilter)>,
-            ),
-        >,
+        input_uniform_holder: &HashMap<&String, (&UniformHolder, InputSampling)>,
+        render_buffers: &HashMap<&String, (&Texture2d, InputSampling)>,
         target: RenderTarget,
         mode_params: &FilterMode,
+        draw_options: &DrawOptions,
+        depth_buffer: Option<&DepthRenderBuffer>,
+        time_elapsed_query: Option<&TimeElapsedQuery>,
     ) -> Result<()> {
-        let instance_attribute_buffer = if let FilterMode::Particles(count) = mode_params {
-            let data = (0..*count)
-                .map(|index| InstanceAttributes {
-                    instance_id: index as i32,
-                })
-                .collect::<Vec<_>>();
-            glium::vertex::VertexBuffer::dynamic(display, &data)
-                .context("Failed to create instance attributes buffer")?
-        } else if let FilterMode::Particles(count) = &self.mode {
-            let data = (0..*count)
-                .map(|index| InstanceAttributes {
-                    instance_id: index as i32,
-                })
-                .collect::<Vec<_>>();
-            glium::vertex::VertexBuffer::immutable(display, &data)
-                .context("Failed to create instance attributes buffer")?
-        } else {
-            let data = vec![InstanceAttributes { instance_id: 0 }];
-            glium::vertex::VertexBuffer::immutable(display, &data)
-                .context("Failed to create instance attributes buffer")?
+        let instance_count = match (mode_params, &self.mode) {
+            (FilterMode::Particles(count), _) | (_, FilterMode::Particles(count)) => *count,
+            _ => 1,
         };
 
-        let mut uniform_vec: Vec<(&String, &dyn AsUniformValue)> = Vec::new();
-        let mut uniform_render_targets_vec = Vec::new();
-        let mut uniform_textures_vec = Vec::new();
-        let mut uniform_srgb_textures_vec = Vec::new();
-        let mut uniform_buffers_vec = Vec::new();
+        {
+            let mut instance_attribute_buffer = self.instance_attribute_buffer.borrow_mut();
+            let buffer_too_small = match instance_attribute_buffer.as_ref() {
+                Some(buffer) => buffer.len() < instance_count,
+                None => true,
+            };
+
+            if buffer_too_small {
+                let data = (0..instance_count)
+                    .map(|index| InstanceAttributes {
+                        instance_id: index as i32,
+                    })
+                    .collect::<Vec<_>>();
+                *instance_attribute_buffer = Some(
+                    VertexBuffer::immutable(display, &data)
+                        .context("Failed to create instance attributes buffer")?,
+                );
+            }
+        }
+
+        let instance_attribute_buffer = self.instance_attribute_buffer.borrow();
+        let instance_attribute_slice = instance_attribute_buffer
+            .as_ref()
+            .and_then(|buffer| buffer.slice(0..instance_count))
+            .context("Failed to slice instance attributes buffer")?;
+
+        // Stages can render at their own size, iResolution follows the buffer being drawn to
+        let resolution_uniform_name = "iResolution".to_owned();
+        let target_resolution = match &target {
+            RenderTarget::FrameBuffer(framebuffer_texture)
+            | RenderTarget::Offscreen(framebuffer_texture) => {
+                let (width, height) = framebuffer_texture.dimensions();
+                Some((width as f32, height as f32, 0.0f32))
+            }
+            RenderTarget::MultiFrameBuffer(framebuffer_texture_list) => framebuffer_texture_list
+                .first()
+                .map(|(_, framebuffer_texture)| {
+                    let (width, height) = framebuffer_texture.dimensions();
+                    (width as f32, height as f32, 0.0f32)
+                }),
+            RenderTarget::Window(_) => None,
+        };
 
+        let mut uniforms_holder = CustomUniforms::default();
         let mut loaded_uniform_name_list = Vec::new();
 
+        if let Some(target_resolution) = &target_resolution {
+            uniforms_holder
+                .primitive_list
+                .push((&resolution_uniform_name, target_resolution));
+            loaded_uniform_name_list.push(resolution_uniform_name.clone());
+        }
+
         for uniform_name in &self.inputs {
-            if let Some((texture, Some((down_sampling, up_sampling)))) =
-                render_buffers.get(uniform_name)
-            {
-                let texture = texture
-                    .sampled()
-                    .wrap_function(SamplerWrapFunction::Repeat)
-                    .minify_filter(*down_sampling)
-                    .magnify_filter(*up_sampling);
-                uniform_render_targets_vec.push((uniform_name, texture));
+            if let Some((texture, Some(sampling))) = render_buffers.get(uniform_name) {
+                uniforms_holder.render_targets_list.push((
+                    uniform_name,
+                    build_sampler(*texture, *sampling, SamplerWrapFunction::Repeat),
+                ));
                 loaded_uniform_name_list.push(uniform_name.clone());
             } else if let Some((value, sampling)) = input_uniform_holder.get(uniform_name) {
-                match value {
-                    UniformHolder::Buffer((texture, _length)) => {
-                        if let Some((down_sampling, up_sampling)) = sampling {
-                            let texture = texture
-                                .sampled()
-                                .wrap_function(SamplerWrapFunction::BorderClamp)
-                                .minify_filter(*down_sampling)
-                                .magnify_filter(*up_sampling);
-                            uniform_buffers_vec.push((uniform_name, texture));
-                        }
-                    }
-                    UniformHolder::Texture((texture, _resolution)) => {
-                        if let Some((down_sampling, up_sampling)) = sampling {
-                            let texture = texture
-                                .sampled()
-                                .wrap_function(SamplerWrapFunction::Repeat)
-                                .minify_filter(*down_sampling)
-                                .magnify_filter(*up_sampling);
-                            uniform_textures_vec.push((uniform_name, texture));
-                        }
-                    }
-                    UniformHolder::SrgbTexture((texture, _resolution)) => {
-                        if let Some((down_sampling, up_sampling)) = sampling {
-                            let texture = texture
-                                .sampled()
-                                .wrap_function(SamplerWrapFunction::Repeat)
-                                .minify_filter(*down_sampling)
-                                .magnify_filter(*up_sampling);
-                            uniform_srgb_textures_vec.push((uniform_name, texture));
-                        }
-                    }
-                    UniformHolder::Float(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Float2(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Float3(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Float4(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Integer(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Bool(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Mat2(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Mat3(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Mat4(value) => uniform_vec.push((uniform_name, value)),
-                }
-
+                uniforms_holder.bind_uniform(uniform_name, value, *sampling);
                 loaded_uniform_name_list.push(uniform_name.clone());
             }
         }
@@ -559,48 +981,7 @@ impl Filter {
             }
 
             if let Some((value, sampling)) = input_uniform_holder.get(uniform_name) {
-                match value {
-                    UniformHolder::Buffer((texture, _length)) => {
-                        if let Some((down_sampling, up_sampling)) = sampling {
-                            let texture = texture
-                                .sampled()
-                                .wrap_function(SamplerWrapFunction::BorderClamp)
-                                .minify_filter(*down_sampling)
-                                .magnify_filter(*up_sampling);
-                            uniform_buffers_vec.push((uniform_name, texture));
-                        }
-                    }
-                    UniformHolder::Texture((texture, _resolution)) => {
-                        if let Some((down_sampling, up_sampling)) = sampling {
-                            let texture = texture
-                                .sampled()
-                                .wrap_function(SamplerWrapFunction::Repeat)
-                                .minify_filter(*down_sampling)
-                                .magnify_filter(*up_sampling);
-                            uniform_textures_vec.push((uniform_name, texture));
-                        }
-                    }
-                    UniformHolder::SrgbTexture((texture, _resolution)) => {
-                        if let Some((down_sampling, up_sampling)) = sampling {
-                            let texture = texture
-                                .sampled()
-                                .wrap_function(SamplerWrapFunction::Repeat)
-                                .minify_filter(*down_sampling)
-                                .magnify_filter(*up_sampling);
-                            uniform_srgb_textures_vec.push((uniform_name, texture));
-                        }
-                    }
-                    UniformHolder::Float(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Float2(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Float3(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Float4(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Integer(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Bool(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Mat2(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Mat3(value) => uniform_vec.push((uniform_name, value)),
-                    UniformHolder::Mat4(value) => uniform_vec.push((uniform_name, value)),
-                }
-
+                uniforms_holder.bind_uniform(uniform_name, value, *sampling);
                 loaded_uniform_name_list.push(uniform_name.clone());
             }
         }
@@ -610,109 +991,177 @@ impl Filter {
                 continue;
             }
 
-            match value {
-                UniformHolder::Buffer((texture, _length)) => {
-                    if let Some((down_sampling, up_sampling)) = sampling {
-                        let texture = texture
-                            .sampled()
-                            .wrap_function(SamplerWrapFunction::BorderClamp)
-                            .minify_filter(*down_sampling)
-                            .magnify_filter(*up_sampling);
-                        uniform_buffers_vec.push((uniform_name, texture));
-                    }
-                }
-                UniformHolder::Texture((texture, _resolution)) => {
-                    if let Some((down_sampling, up_sampling)) = sampling {
-                        let texture = texture
-                            .sampled()
-                            .wrap_function(SamplerWrapFunction::Repeat)
-                            .minify_filter(*down_sampling)
-                            .magnify_filter(*up_sampling);
-                        uniform_textures_vec.push((uniform_name, texture));
-                    }
-                }
-                UniformHolder::SrgbTexture((texture, _resolution)) => {
-                    if let Some((down_sampling, up_sampling)) = sampling {
-                        let texture = texture
-                            .sampled()
-                            .wrap_function(SamplerWrapFunction::Repeat)
-                            .minify_filter(*down_sampling)
-                            .magnify_filter(*up_sampling);
-                        uniform_srgb_textures_vec.push((uniform_name, texture));
-                    }
-                }
-                UniformHolder::Float(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Float2(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Float3(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Float4(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Integer(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Bool(value) => uniform_vec.push((uniform_name, value)),
-
-                UniformHolder::Mat2(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Mat3(value) => uniform_vec.push((uniform_name, value)),
-                UniformHolder::Mat4(value) => uniform_vec.push((uniform_name, value)),
-            }
+            uniforms_holder.bind_uniform(uniform_name, value, *sampling);
         }
 
-        let uniforms_holder = CustomUniforms {
-            primitive_list: uniform_vec,
-            render_targets_list: uniform_render_targets_vec,
-            texture_list: uniform_textures_vec,
-            srgb_texture_list: uniform_srgb_textures_vec,
-            buffer_list: uniform_buffers_vec,
+        let blend_mode = match (draw_options.blend_mode, &self.mode) {
+            (Some(blend_mode), _) => blend_mode,
+            (None, FilterMode::Particles(_)) => BlendMode::Additive,
+            (None, _) => BlendMode::Replace,
         };
 
-        let draw_params = if let FilterMode::Particles(_) = self.mode {
-            glium::DrawParameters {
-                blend: glium::Blend {
-                    color: glium::BlendingFunction::Addition {
-                        source: glium::LinearBlendingFactor::One,
-                        destination: glium::LinearBlendingFactor::One,
-                    },
-                    alpha: glium::BlendingFunction::Addition {
-                        source: glium::LinearBlendingFactor::One,
-                        destination: glium::LinearBlendingFactor::One,
-                    },
-                    constant_value: (1.0, 1.0, 1.0, 1.0),
-                },
+        // Depth testing needs a depth buffer, the window relies on the one of its context
+        let depth_test = match target {
+            RenderTarget::Window(_) => draw_options.depth_test,
+            _ => draw_options.depth_test.filter(|_| depth_buffer.is_some()),
+        };
+        let depth_buffer = depth_buffer.filter(|_| depth_test.is_some());
+
+        let mut draw_params = glium::DrawParameters {
+            blend: blend_mode.to_blend(),
+            ..Default::default()
+        };
+        if let Some(depth_test) = depth_test {
+            draw_params.depth = Depth {
+                test: depth_test,
+                write: draw_options.depth_write,
                 ..Default::default()
-            }
-        } else {
-            Default::default()
+            };
+        }
+        draw_params.time_elapsed_query = time_elapsed_query;
+
+        let clear_color = match draw_options.clear_mode {
+            ClearMode::Default => match target {
+                RenderTarget::Offscreen(_) | RenderTarget::Window(_) => Some((0.0, 0.0, 0.0, 1.0)),
+                _ => Some((0.0, 0.0, 0.0, 0.0)),
+            },
+            ClearMode::Color(clear_color) => Some(clear_color),
+            ClearMode::Keep | ClearMode::Accumulate(_) => None,
         };
+        let clear_depth = depth_test.map(|_| 1.0);
 
         match target {
             RenderTarget::FrameBuffer(framebuffer_texture) => {
-                let mut framebuffer = SimpleFrameBuffer::new(display, framebuffer_texture)
-                    .context("Failed to create target buffer for rendering")?;
-                framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+                let mut framebuffer = match depth_buffer {
+                    Some(depth_buffer) => SimpleFrameBuffer::with_depth_buffer(
+                        display,
+                        framebuffer_texture,
+                        depth_buffer,
+                    ),
+                    None => SimpleFrameBuffer::new(display, framebuffer_texture),
+                }
+                .context("Failed to create target buffer for rendering")?;
+                framebuffer.clear(None, clear_color, false, clear_depth, None);
 
                 framebuffer
                     .draw(
                         (
                             &self.vertex_buffer,
-                            instance_attribute_buffer.per_instance().unwrap(),
+                            instance_attribute_slice.per_instance().unwrap(),
                         ),
                         &self.index_buffer,
                         &self.program,
                         &uniforms_holder,
                         &draw_params,
                     )
+                    .map_err(|e| describe_draw_error(e, &uniforms_holder))
                     .context("Failed to render filter to framebuffer")?;
             }
+            RenderTarget::MultiFrameBuffer(framebuffer_texture_list) => {
+                // Outputs unknown to the current program, e.g. while the fallback program is
+                // in use, are left untouched
+                let attachment_list: Vec<(&str, &Texture2d)> = framebuffer_texture_list
+                    .iter()
+                    .filter(|(output_name, _)| {
+                        self.program.get_frag_data_location(output_name).is_some()
+                    })
+                    .map(|(output_name, texture)| (output_name.as_str(), *texture))
+                    .collect();
+
+                if attachment_list.is_empty() {
+                    if let Some((_, framebuffer_texture)) = framebuffer_texture_list.first() {
+                        let mut framebuffer = match depth_buffer {
+                            Some(depth_buffer) => SimpleFrameBuffer::with_depth_buffer(
+                                display,
+                                *framebuffer_texture,
+                                depth_buffer,
+                            ),
+                            None => SimpleFrameBuffer::new(display, *framebuffer_texture),
+                        }
+                        .context("Failed to create target buffer for rendering")?;
+                        framebuffer.clear(None, clear_color, false, clear_depth, None);
+
+                        framebuffer
+                            .draw(
+                                (
+                                    &self.vertex_buffer,
+                                    instance_attribute_slice.per_instance().unwrap(),
+                                ),
+                                &self.index_buffer,
+                                &self.program,
+                                &uniforms_holder,
+                                &draw_params,
+                            )
+                            .map_err(|e| describe_draw_error(e, &uniforms_holder))
+                            .context("Failed to render filter to framebuffer")?;
+                    }
+                } else {
+                    let mut framebuffer = match depth_buffer {
+                        Some(depth_buffer) => MultiOutputFrameBuffer::with_depth_buffer(
+                            display,
+                            attachment_list,
+                            depth_buffer,
+                        ),
+                        None => MultiOutputFrameBuffer::new(display, attachment_list),
+                    }
+                    .context("Failed to create target buffers for rendering")?;
+                    framebuffer.clear(None, clear_color, false, clear_depth, None);
+
+                    framebuffer
+                        .draw(
+                            (
+                                &self.vertex_buffer,
+                                instance_attribute_slice.per_instance().unwrap(),
+                            ),
+                            &self.index_buffer,
+                            &self.program,
+                            &uniforms_holder,
+                            &draw_params,
+                        )
+                        .map_err(|e| describe_draw_error(e, &uniforms_holder))
+                        .context("Failed to render filter to framebuffers")?;
+                }
+            }
+            RenderTarget::Offscreen(offscreen_texture) => {
+                let mut framebuffer = match depth_buffer {
+                    Some(depth_buffer) => SimpleFrameBuffer::with_depth_buffer(
+                        display,
+                        offscreen_texture,
+                        depth_buffer,
+                    ),
+                    None => SimpleFrameBuffer::new(display, offscreen_texture),
+                }
+                .context("Failed to create offscreen buffer for rendering")?;
+                framebuffer.clear(None, clear_color, false, clear_depth, None);
+
+                framebuffer
+                    .draw(
+                        (
+                            &self.vertex_buffer,
+                            instance_attribute_slice.per_instance().unwrap(),
+                        ),
+                        &self.index_buffer,
+                        &self.program,
+                        &uniforms_holder,
+                        &draw_params,
+                    )
+                    .map_err(|e| describe_draw_error(e, &uniforms_holder))
+                    .context("Failed to render filter to offscreen buffer")?;
+            }
             RenderTarget::Window(window_frame) => {
-                window_frame.clear_color(0.0, 0.0, 0.0, 1.0);
+                window_frame.clear(None, clear_color, false, clear_depth, None);
                 window_frame
                     .draw(
                         (
                             &self.vertex_buffer,
-                            instance_attribute_buffer.per_instance().unwrap(),
+                            instance_attribute_slice.per_instance().unwrap(),
                         ),
                         &self.index_buffer,
                         &self.program,
                         &uniforms_holder,
                         &draw_params,
                     )
+                    .map_err(|e| describe_draw_error(e, &uniforms_holder))
                     .context("Failed to render filter to display")?;
             }
         }
@@ -0,0 +1,345 @@
+use std::collections::BTreeSet;
+
+use wvr_data::types::InputSampler;
+
+use crate::stage::Stage;
+
+pub fn get_input_source_name(input: &InputSampler) -> &String {
+    match input {
+        InputSampler::Nearest(input_name) => input_name,
+        InputSampler::Linear(input_name) => input_name,
+        InputSampler::Mipmaps(input_name) => input_name,
+    }
+}
+
+/// Splits an input name of the form `stage_name@N` into the stage name and a frame offset.
+pub fn parse_history_reference(input_name: &str) -> (&str, usize) {
+    if let Some((stage_name, frame_offset)) = input_name.rsplit_once('@') {
+        if !stage_name.is_empty() {
+            if let Ok(frame_offset) = frame_offset.parse() {
+                return (stage_name, frame_offset);
+            }
+        }
+    }
+
+    (input_name, 0)
+}
+
+/// Finds the stage an input reads from, the named output it reads if any (`stage_name.output`)
+/// and how many frames back it reads.
+pub fn find_input_stage<'a>(
+    render_chain: &[Stage],
+    input_name: &'a str,
+) -> Option<(usize, Option<&'a str>, usize)> {
+    let find_stage = |stage_name: &str| {
+        render_chain
+            .iter()
+            .rposition(|stage| stage.get_name() == stage_name)
+    };
+
+    let (stage_name, frame_offset) = parse_history_reference(input_name);
+    if let Some(stage_index) = find_stage(stage_name) {
+        return Some((stage_index, None, frame_offset));
+    }
+
+    let (stage_name, output_name) = stage_name.rsplit_once('.')?;
+    find_stage(stage_name).map(|stage_index| (stage_index, Some(output_name), frame_offset))
+}
+
+/// Render order of a render chain, derived from the inputs each stage reads.
+///
+/// Inputs marked as feedback, history inputs (`stage_name@N`) and stages reading their own
+/// output read past frames and do not constrain the order. Any other cycle is broken arbitrarily and reported.
+pub struct RenderGraph {
+    render_order: Vec<usize>,
+    unmarked_cycle_list: Vec<Vec<String>>,
+}
+
+impl RenderGraph {
+    pub fn from_stages(stage_list: &[Stage]) -> Self {
+        let mut successor_list = vec![Vec::new(); stage_list.len()];
+        let mut dependency_count_list = vec![0; stage_list.len()];
+
+        for (stage_index, stage) in stage_list.iter().enumerate() {
+            for (uniform_name, input) in stage.get_input_map() {
+                if stage.is_feedback_input(uniform_name) {
+                    continue;
+                }
+
+                if let Some((input_index, _, 0)) =
+                    find_input_stage(stage_list, get_input_source_name(input))
+                {
+                    if input_index != stage_index
+                        && !successor_list[input_index].contains(&stage_index)
+                    {
+                        successor_list[input_index].push(stage_index);
+                        dependency_count_list[stage_index] += 1;
+                    }
+                }
+            }
+        }
+
+        let unmarked_cycle_list = find_cycles(&successor_list)
+            .into_iter()
+            .map(|cycle| {
+                cycle
+                    .into_iter()
+                    .map(|stage_index| stage_list[stage_index].get_name().clone())
+                    .collect()
+            })
+            .collect();
+
+        let mut render_order = Vec::with_capacity(stage_list.len());
+        let mut remaining_list: BTreeSet<usize> = (0..stage_list.len()).collect();
+        let mut ready_list: BTreeSet<usize> = remaining_list
+            .iter()
+            .copied()
+            .filter(|&stage_index| dependency_count_list[stage_index] == 0)
+            .collect();
+
+        while let Some(&first_remaining) = remaining_list.iter().next() {
+            // When only cycles remain, the earliest stage in the chain is rendered first
+            let stage_index = match ready_list.iter().next() {
+                Some(&stage_index) => stage_index,
+                None => first_remaining,
+            };
+
+            ready_list.remove(&stage_index);
+            remaining_list.remove(&stage_index);
+            render_order.push(stage_index);
+
+            for &successor_index in &successor_list[stage_index] {
+                if !remaining_list.contains(&successor_index) {
+                    continue;
+                }
+
+                dependency_count_list[successor_index] -= 1;
+                if dependency_count_list[successor_index] == 0 {
+                    ready_list.insert(successor_index);
+                }
+            }
+        }
+
+        Self {
+            render_order,
+            unmarked_cycle_list,
+        }
+    }
+
+    pub fn get_render_order(&self) -> &[usize] {
+        &self.render_order
+    }
+
+    pub fn get_unmarked_cycle_list(&self) -> &[Vec<String>] {
+        &self.unmarked_cycle_list
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.unmarked_cycle_list.is_empty()
+    }
+}
+
+struct CycleSearch<'a> {
+    successor_list: &'a [Vec<usize>],
+    next_order: usize,
+    order_list: Vec<Option<usize>>,
+    low_link_list: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    cycle_list: Vec<Vec<usize>>,
+}
+
+/// Lists the strongly connected components of more than one stage.
+fn find_cycles(successor_list: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut search = CycleSearch {
+        successor_list,
+        next_order: 0,
+        order_list: vec![None; successor_list.len()],
+        low_link_list: vec![0; successor_list.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; successor_list.len()],
+        cycle_list: Vec::new(),
+    };
+
+    for stage_index in 0..successor_list.len() {
+        if search.order_list[stage_index].is_none() {
+            search.visit(stage_index);
+        }
+    }
+
+    search.cycle_list
+}
+
+impl<'a> CycleSearch<'a> {
+    fn visit(&mut self, stage_index: usize) {
+        self.order_list[stage_index] = Some(self.next_order);
+        self.low_link_list[stage_index] = self.next_order;
+        self.next_order += 1;
+
+        self.stack.push(stage_index);
+        self.on_stack[stage_index] = true;
+
+        for &successor_index in &self.successor_list[stage_index] {
+            match self.order_list[successor_index] {
+                None => {
+                    self.visit(successor_index);
+                    self.low_link_list[stage_index] =
+                        self.low_link_list[stage_index].min(self.low_link_list[successor_index]);
+                }
+                Some(successor_order) if self.on_stack[successor_index] => {
+                    self.low_link_list[stage_index] =
+                        self.low_link_list[stage_index].min(successor_order);
+                }
+                Some(_) => (),
+            }
+        }
+
+        if Some(self.low_link_list[stage_index]) == self.order_list[stage_index] {
+            let mut component = Vec::new();
+            while let Some(member_index) = self.stack.pop() {
+                self.on_stack[member_index] = false;
+                component.push(member_index);
+
+                if member_index == stage_index {
+                    break;
+                }
+            }
+
+            if component.len() > 1 {
+                component.sort_unstable();
+                self.cycle_list.push(component);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glium::texture::UncompressedFloatFormat;
+
+    use wvr_data::config::filter::FilterMode;
+    use wvr_data::types::InputSampler;
+
+    use super::*;
+
+    /// A stage reading each of `input_list` through uniforms named `input_0`, `input_1`...
+    fn build_stage(name: &str, input_list: &[&str]) -> Stage {
+        let input_map = input_list
+            .iter()
+            .enumerate()
+            .map(|(index, input_name)| {
+                (
+                    format!("input_{}", index),
+                    InputSampler::Linear(input_name.to_string()),
+                )
+            })
+            .collect();
+
+        Stage::new(
+            name,
+            UncompressedFloatFormat::U8U8U8U8,
+            "filter",
+            FilterMode::Particles(1),
+            input_map,
+            HashMap::new(),
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn orders_stages_after_their_inputs() {
+        let stage_list = vec![
+            build_stage("composite", &["blur"]),
+            build_stage("source", &[]),
+            build_stage("blur", &["source"]),
+        ];
+
+        let render_graph = RenderGraph::from_stages(&stage_list);
+
+        assert_eq!(render_graph.get_render_order(), &[1, 2, 0]);
+        assert!(render_graph.is_valid());
+    }
+
+    #[test]
+    fn keeps_chain_order_of_independent_stages() {
+        let stage_list = vec![
+            build_stage("a", &[]),
+            build_stage("b", &["external_input"]),
+            build_stage("c", &[]),
+        ];
+
+        let render_graph = RenderGraph::from_stages(&stage_list);
+
+        assert_eq!(render_graph.get_render_order(), &[0, 1, 2]);
+        assert!(render_graph.is_valid());
+    }
+
+    #[test]
+    fn reports_unmarked_cycles() {
+        let stage_list = vec![
+            build_stage("source", &[]),
+            build_stage("a", &["b", "source"]),
+            build_stage("b", &["a"]),
+            build_stage("c", &["b"]),
+        ];
+
+        let render_graph = RenderGraph::from_stages(&stage_list);
+
+        assert!(!render_graph.is_valid());
+        assert_eq!(
+            render_graph.get_unmarked_cycle_list(),
+            &[vec!["a".to_owned(), "b".to_owned()]]
+        );
+        // The cycle is broken at its earliest stage and every stage is still rendered once
+        assert_eq!(render_graph.get_render_order(), &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn feedback_inputs_do_not_form_cycles() {
+        let mut stage_list = vec![build_stage("a", &["b"]), build_stage("b", &["a"])];
+        stage_list[1].set_input_feedback("input_0", true);
+
+        let render_graph = RenderGraph::from_stages(&stage_list);
+
+        assert!(render_graph.is_valid());
+        assert!(render_graph.get_unmarked_cycle_list().is_empty());
+        assert_eq!(render_graph.get_render_order(), &[1, 0]);
+    }
+
+    #[test]
+    fn self_and_history_inputs_do_not_form_cycles() {
+        let stage_list = vec![build_stage("a", &["a", "b@1"]), build_stage("b", &["a"])];
+
+        let render_graph = RenderGraph::from_stages(&stage_list);
+
+        assert!(render_graph.is_valid());
+        assert_eq!(render_graph.get_render_order(), &[0, 1]);
+    }
+
+    #[test]
+    fn parses_history_references() {
+        assert_eq!(parse_history_reference("blur@2"), ("blur", 2));
+        assert_eq!(parse_history_reference("blur"), ("blur", 0));
+        assert_eq!(parse_history_reference("blur.bloom@1"), ("blur.bloom", 1));
+        assert_eq!(parse_history_reference("a@b@3"), ("a@b", 3));
+    }
+
+    #[test]
+    fn keeps_invalid_history_references_as_names() {
+        assert_eq!(parse_history_reference("@2"), ("@2", 0));
+        assert_eq!(parse_history_reference("blur@"), ("blur@", 0));
+        assert_eq!(parse_history_reference("blur@-1"), ("blur@-1", 0));
+        assert_eq!(parse_history_reference("blur@last"), ("blur@last", 0));
+    }
+
+    #[test]
+    fn finds_history_inputs() {
+        let stage_list = vec![build_stage("source", &[]), build_stage("blur", &[])];
+
+        assert_eq!(find_input_stage(&stage_list, "blur@3"), Some((1, None, 3)));
+        assert_eq!(find_input_stage(&stage_list, "source"), Some((0, None, 0)));
+        assert_eq!(find_input_stage(&stage_list, "missing@1"), None);
+    }
+}
@@ -3,18 +3,27 @@ extern crate glium;
 extern crate wvr_data;
 
 use std::borrow::Cow;
-use std::collections::hash_map::HashMap;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::time::Instant;
 use std::vec::Vec;
 
 use anyhow::{Context, Result};
 
+use glium::draw_parameters::TimeElapsedQuery;
+use glium::framebuffer::DepthRenderBuffer;
+use glium::texture::DepthFormat;
 use glium::texture::MipmapsOption;
 use glium::texture::Texture2d;
 use glium::texture::Texture2dDataSink;
+use glium::texture::UncompressedFloatFormat;
 use glium::uniforms::MagnifySamplerFilter;
 use glium::Frame;
+use glium::Rect;
+use glium::Surface;
 use glium::{backend::Facade, uniforms::MinifySamplerFilter};
 
 use wvr_data::config::filter::FilterConfig;
@@ -23,13 +32,40 @@ use wvr_data::config::rendering::RenderStageConfig;
 use wvr_data::types::DataHolder;
 use wvr_data::types::{InputProvider, InputSampler};
 
+pub mod accumulation;
+pub mod error;
+pub mod export;
+pub mod fallback;
 pub mod filter;
+pub mod graph;
+pub mod libs;
+pub mod offline;
+pub mod profiler;
+pub mod readback;
+pub mod recorder;
+pub mod source_map;
 pub mod stage;
 pub mod uniform;
 
-use filter::{Filter, RenderTarget};
-use stage::Stage;
-use uniform::UniformHolder;
+#[cfg(test)]
+mod test_util;
+
+use accumulation::DecayPass;
+use export::{ExportFormat, ExportOptions};
+use filter::{ClearMode, Filter, FilterEvent, FilterStatus, RenderTarget};
+use graph::{find_input_stage, RenderGraph};
+use profiler::{FrameStats, Profiler};
+use readback::{ReadbackFrame, ReadbackQueue};
+use stage::{compute_stage_resolution, Stage};
+use uniform::{TextureOptions, UniformHolder};
+
+/// Filter events waiting for the host, newer events are dropped once it is full so that an
+/// event receiver taken late or never does not grow without bound.
+const FILTER_EVENT_CAPACITY: usize = 256;
+
+/// Buffers of a stage, from the most recent frame to the oldest one and one texture per
+/// output each, along with their resolution.
+type RenderBufferPack = (Vec<Vec<Texture2d>>, (u32, u32));
 
 pub struct RGBAImageData {
     pub data: Vec<(u8, u8, u8, u8)>,
@@ -47,8 +83,113 @@ impl Texture2dDataSink<(u8, u8, u8, u8)> for RGBAImageData {
     }
 }
 
+pub struct RGBAFloatImageData {
+    pub data: Vec<(f32, f32, f32, f32)>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Texture2dDataSink<(f32, f32, f32, f32)> for RGBAFloatImageData {
+    fn from_raw(data: Cow<[(f32, f32, f32, f32)]>, width: u32, height: u32) -> Self {
+        RGBAFloatImageData {
+            data: data.into_owned(),
+            width,
+            height,
+        }
+    }
+}
+
+fn get_output_list<'a>(filter_list: &'a HashMap<String, Filter>, stage: &Stage) -> &'a [String] {
+    filter_list
+        .get(stage.get_filter())
+        .map(Filter::get_output_list)
+        .unwrap_or(&[])
+}
+
+fn create_render_buffers(
+    display: &dyn Facade,
+    stage: &Stage,
+    resolution: (u32, u32),
+    output_count: usize,
+) -> Result<RenderBufferPack> {
+    let mut texture_list = Vec::new();
+    for _ in 0..stage.get_history_depth() + 1 {
+        let mut output_texture_list = Vec::new();
+        for _ in 0..output_count.max(1) {
+            output_texture_list.push(
+                Texture2d::empty_with_format(
+                    display,
+                    stage.get_buffer_format(),
+                    MipmapsOption::EmptyMipmaps,
+                    resolution.0,
+                    resolution.1,
+                )
+                .context("Failed to create a rendering buffer")?,
+            );
+        }
+
+        texture_list.push(output_texture_list);
+    }
+
+    Ok((texture_list, resolution))
+}
+
+fn clear_render_buffers(render_buffer_pack: &RenderBufferPack) {
+    for texture in render_buffer_pack.0.iter().flatten() {
+        texture.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+    }
+}
+
+fn full_texture_rect(texture: &Texture2d) -> Rect {
+    let (width, height) = texture.dimensions();
+
+    Rect {
+        left: 0,
+        bottom: 0,
+        width,
+        height,
+    }
+}
+
+fn read_texture_region(texture: &Texture2d, region: &Rect) -> Result<RGBAImageData> {
+    let (width, height) = texture.dimensions();
+    let fits = |start: u32, size: u32, limit: u32| {
+        size > 0 && start.checked_add(size).is_some_and(|end| end <= limit)
+    };
+
+    if !fits(region.left, region.width, width) || !fits(region.bottom, region.height, height) {
+        anyhow::bail!(
+            "Region {}x{} at ({}, {}) is outside of the {}x{} buffer",
+            region.width,
+            region.height,
+            region.left,
+            region.bottom,
+            width,
+            height
+        );
+    }
+
+    let image = texture
+        .main_level()
+        .first_layer()
+        .into_image(None)
+        .context("Could not access texture image")?;
+
+    // raw_read reports the size of the whole texture instead of the size of the region read
+    let image_data: RGBAImageData = image.raw_read(region);
+
+    Ok(RGBAImageData {
+        data: image_data.data,
+        width: region.width,
+        height: region.height,
+    })
+}
+
 pub struct ShaderView {
     uniform_holder: HashMap<String, UniformHolder>,
+    /// Inputs whose last value could not be converted, reported once until a value converts.
+    ignored_input_list: HashSet<String>,
+    input_texture_options_list: HashMap<String, TextureOptions>,
 
     resolution: (usize, usize),
     mouse_position: (f64, f64),
@@ -56,9 +197,20 @@ pub struct ShaderView {
     dynamic: bool,
 
     filter_list: HashMap<String, Filter>,
-    render_buffer_list: Vec<(Vec<Texture2d>, (u32, u32))>,
+    filter_event_sender: SyncSender<(String, FilterEvent)>,
+    filter_event_receiver: Option<Receiver<(String, FilterEvent)>>,
+
+    render_buffer_list: Vec<RenderBufferPack>,
     render_chain: Vec<Stage>,
+    rendered_stage_list: Vec<bool>,
     final_stage: Stage,
+    final_buffer: Option<Texture2d>,
+    depth_buffer_list: HashMap<(u32, u32), DepthRenderBuffer>,
+    decay_pass: Option<DecayPass>,
+
+    readback_queue_list: Vec<ReadbackQueue>,
+
+    profiler: Option<Profiler>,
 }
 
 impl ShaderView {
@@ -75,54 +227,74 @@ impl ShaderView {
         let mut filter_list = HashMap::new();
         let mut render_buffer_list = Vec::new();
 
+        let (filter_event_sender, filter_event_receiver) = sync_channel(FILTER_EVENT_CAPACITY);
+
         for (filter_name, (filter_path, filter_config, system_filter)) in filters {
-            let filter = Filter::from_config(
+            match Filter::from_config(
                 &[&filter_path.join("src"), &wvr_data::get_libs_path()],
                 filter_config,
                 display,
                 resolution,
                 *system_filter,
-            )?;
-            filter_list.insert(filter_name.clone(), filter);
+            ) {
+                Ok(filter) => {
+                    if let FilterStatus::Fallback(diagnostics) = filter.get_status() {
+                        let _ = filter_event_sender.try_send((
+                            filter_name.clone(),
+                            FilterEvent::FilterCompileFailed {
+                                diagnostics: diagnostics.clone(),
+                            },
+                        ));
+                    }
+
+                    filter_list.insert(filter_name.clone(), filter);
+                }
+                Err(e) => {
+                    let source_missing = e
+                        .downcast_ref::<std::io::Error>()
+                        .map(|e| e.kind() == std::io::ErrorKind::NotFound)
+                        .unwrap_or(false);
+                    let event = if source_missing {
+                        FilterEvent::FilterSourceMissing {
+                            message: e.to_string(),
+                        }
+                    } else {
+                        FilterEvent::FilterLoadFailed {
+                            message: format!("{:#}", e),
+                        }
+                    };
+
+                    let _ = filter_event_sender.try_send((filter_name.clone(), event));
+                }
+            }
         }
 
         for render_stage_config in render_chain {
-            let mut stage =
-                Stage::from_config(&render_stage_config.name, display, render_stage_config)
-                    .context("Failed to build render stage")?;
-
-            render_buffer_list.push((
-                vec![
-                    Texture2d::empty_with_format(
-                        display,
-                        stage.get_buffer_format(),
-                        MipmapsOption::EmptyMipmaps,
-                        resolution.0 as u32,
-                        resolution.1 as u32,
-                    )
-                    .context("Failed to create a rendering buffer")?,
-                    Texture2d::empty_with_format(
-                        display,
-                        stage.get_buffer_format(),
-                        MipmapsOption::EmptyMipmaps,
-                        resolution.0 as u32,
-                        resolution.1 as u32,
-                    )
-                    .context("Failed to create a rendering buffer")?,
-                ],
-                (resolution.0 as u32, resolution.1 as u32),
-            ));
-
-            stage.recreate_buffers = false;
+            let stage = Stage::from_config(&render_stage_config.name, display, render_stage_config)
+                .context("Failed to build render stage")?;
 
             view_chain.push(stage);
         }
 
+        for stage_index in 0..view_chain.len() {
+            let stage_resolution = compute_stage_resolution(&view_chain, stage_index, resolution);
+            render_buffer_list.push(create_render_buffers(
+                display,
+                &view_chain[stage_index],
+                stage_resolution,
+                get_output_list(&filter_list, &view_chain[stage_index]).len(),
+            )?);
+
+            view_chain[stage_index].recreate_buffers = false;
+        }
+
         let final_stage = Stage::from_config(&final_stage_config.name, display, final_stage_config)
             .context("Failed to build final render stage")?;
 
         Ok(Self {
             uniform_holder: HashMap::new(),
+            ignored_input_list: HashSet::new(),
+            input_texture_options_list: HashMap::new(),
 
             resolution,
             mouse_position: (0.0, 0.0),
@@ -130,12 +302,41 @@ impl ShaderView {
             dynamic: view_config.dynamic,
 
             filter_list,
+            filter_event_sender,
+            filter_event_receiver: Some(filter_event_receiver),
+
             render_buffer_list,
             render_chain: view_chain,
+            rendered_stage_list: Vec::new(),
             final_stage,
+            final_buffer: None,
+            depth_buffer_list: HashMap::new(),
+            decay_pass: None,
+
+            readback_queue_list: Vec::new(),
+
+            profiler: None,
         })
     }
 
+    /// Hands over the receiving end of the filter event channel, only available once.
+    pub fn take_filter_event_receiver(&mut self) -> Option<Receiver<(String, FilterEvent)>> {
+        self.filter_event_receiver.take()
+    }
+
+    /// Clears every stage buffer, history and feedback frames included, and the final buffer
+    /// to transparent black, so that the next frames render the same as after a fresh start.
+    pub fn reset_buffers(&mut self) {
+        for render_buffer_pack in &self.render_buffer_list {
+            clear_render_buffers(render_buffer_pack);
+        }
+        if let Some(final_buffer) = &self.final_buffer {
+            final_buffer.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+        }
+
+        self.rendered_stage_list.clear();
+    }
+
     pub fn set_mouse_position(&mut self, position: (f64, f64)) {
         self.mouse_position = position;
     }
@@ -153,30 +354,20 @@ impl ShaderView {
         self.render_chain.insert(target_index, render_stage);
     }
 
-    pub fn add_render_stage(&mut self, display: &dyn Facade, stage: Stage) -> Result<()> {
-        self.render_buffer_list.push((
-            vec![
-                Texture2d::empty_with_format(
-                    display,
-                    stage.get_buffer_format(),
-                    MipmapsOption::EmptyMipmaps,
-                    self.resolution.0 as u32,
-                    self.resolution.1 as u32,
-                )
-                .context("Failed to create a rendering buffer")?,
-                Texture2d::empty_with_format(
-                    display,
-                    stage.get_buffer_format(),
-                    MipmapsOption::EmptyMipmaps,
-                    self.resolution.0 as u32,
-                    self.resolution.1 as u32,
-                )
-                .context("Failed to create a rendering buffer")?,
-            ],
-            (self.resolution.0 as u32, self.resolution.1 as u32),
-        ));
+    pub fn add_render_stage(&mut self, display: &dyn Facade, mut stage: Stage) -> Result<()> {
+        stage.recreate_buffers = false;
         self.render_chain.push(stage);
 
+        let stage_index = self.render_chain.len() - 1;
+        let stage_resolution =
+            compute_stage_resolution(&self.render_chain, stage_index, self.resolution);
+        self.render_buffer_list.push(create_render_buffers(
+            display,
+            &self.render_chain[stage_index],
+            stage_resolution,
+            get_output_list(&self.filter_list, &self.render_chain[stage_index]).len(),
+        )?);
+
         Ok(())
     }
 
@@ -210,6 +401,7 @@ impl ShaderView {
             }
         }
 
+        let upload_start = self.profiler.as_ref().map(|_| Instant::now());
         for (input_name, source) in uniform_sources.iter_mut() {
             for source_id in &source.provides() {
                 if let Some(ref value) = source.get(source_id, true) {
@@ -218,54 +410,73 @@ impl ShaderView {
                     } else {
                         source_id.clone()
                     };
-                    if let Ok(value) = UniformHolder::try_from((
-                        display as &dyn Facade,
-                        value,
-                        texture_with_mipmap_list.contains(&source_id),
-                    )) {
-                        self.uniform_holder.insert(source_id, value);
+                    let generate_mipmaps = texture_with_mipmap_list.contains(&source_id);
+                    let texture_options = self.get_input_texture_options(&source_id);
+
+                    let written = self.uniform_holder.get(&source_id).is_some_and(|holder| {
+                        holder.write(value, generate_mipmaps, texture_options)
+                    });
+
+                    if written {
+                        self.ignored_input_list.remove(&source_id);
+                    } else {
+                        match UniformHolder::try_from((
+                            display as &dyn Facade,
+                            value,
+                            generate_mipmaps,
+                            texture_options,
+                        )) {
+                            Ok(value) => {
+                                self.ignored_input_list.remove(&source_id);
+                                self.uniform_holder.insert(source_id, value);
+                            }
+                            Err(e) => {
+                                if self.ignored_input_list.insert(source_id.clone()) {
+                                    eprintln!("Ignoring input {}: {:?}", source_id, e);
+                                }
+                            }
+                        }
                     }
                 }
             }
         }
+        if let (Some(profiler), Some(upload_start)) = (&mut self.profiler, upload_start) {
+            profiler.push_uniform_upload_time(upload_start.elapsed());
+        }
 
-        for filter in self.filter_list.values_mut() {
+        for (filter_name, filter) in self.filter_list.iter_mut() {
             filter.set_time(time);
             filter.set_beat(beat);
             filter.set_frame_count(frame_count);
             filter.set_mouse_position(self.mouse_position);
             filter.set_resolution(self.resolution);
 
-            filter.update(display);
+            for event in filter.update(display) {
+                let _ = self
+                    .filter_event_sender
+                    .try_send((filter_name.clone(), event));
+            }
         }
 
+        let stage_resolution_list: Vec<(u32, u32)> = (0..self.render_chain.len())
+            .map(|stage_index| {
+                compute_stage_resolution(&self.render_chain, stage_index, self.resolution)
+            })
+            .collect();
+
         for (stage_index, ref mut stage) in self.render_chain.iter_mut().enumerate() {
-            if stage.recreate_buffers {
-                self.render_buffer_list.remove(stage_index);
-                self.render_buffer_list.insert(
-                    stage_index,
-                    (
-                        vec![
-                            Texture2d::empty_with_format(
-                                display,
-                                stage.get_buffer_format(),
-                                MipmapsOption::EmptyMipmaps,
-                                self.resolution.0 as u32,
-                                self.resolution.1 as u32,
-                            )
-                            .context("Failed to create a rendering buffer")?,
-                            Texture2d::empty_with_format(
-                                display,
-                                stage.get_buffer_format(),
-                                MipmapsOption::EmptyMipmaps,
-                                self.resolution.0 as u32,
-                                self.resolution.1 as u32,
-                            )
-                            .context("Failed to create a rendering buffer")?,
-                        ],
-                        (self.resolution.0 as u32, self.resolution.1 as u32),
-                    ),
-                );
+            let stage_resolution = stage_resolution_list[stage_index];
+
+            let output_count = get_output_list(&self.filter_list, stage).len().max(1);
+            let (allocated_texture_list, allocated_resolution) =
+                &self.render_buffer_list[stage_index];
+
+            if stage.recreate_buffers
+                || *allocated_resolution != stage_resolution
+                || allocated_texture_list[0].len() != output_count
+            {
+                self.render_buffer_list[stage_index] =
+                    create_render_buffers(display, stage, stage_resolution, output_count)?;
 
                 stage.recreate_buffers = false;
             }
@@ -277,39 +488,158 @@ impl ShaderView {
     }
 
     pub fn render_stages(&mut self, display: &dyn Facade) -> Result<()> {
-        let mut texture_with_mipmap_list: Vec<String> = Vec::new();
-        for render_stage in &self.render_chain {
+        let mut stage_with_mipmap_list: Vec<usize> = Vec::new();
+        for render_stage in self.render_chain.iter().chain(Some(&self.final_stage)) {
             for texture_sampling in render_stage.get_input_map().values() {
                 if let InputSampler::Mipmaps(texture_name) = texture_sampling {
-                    texture_with_mipmap_list.push(texture_name.to_owned());
+                    if let Some((stage_index, _, _)) =
+                        find_input_stage(&self.render_chain, texture_name)
+                    {
+                        stage_with_mipmap_list.push(stage_index);
+                    }
                 }
             }
         }
-        for texture_sampling in self.final_stage.get_input_map().values() {
-            if let InputSampler::Mipmaps(texture_name) = texture_sampling {
-                texture_with_mipmap_list.push(texture_name.to_owned());
-            }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.collect_queries();
+            profiler.retain_stages(
+                &self
+                    .render_chain
+                    .iter()
+                    .chain(Some(&self.final_stage))
+                    .map(|stage| stage.get_name().as_str())
+                    .collect(),
+            );
+        }
+
+        self.update_depth_buffers(display)?;
+
+        if self.decay_pass.is_none()
+            && self.render_chain.iter().any(|stage| {
+                matches!(
+                    stage.get_draw_options().clear_mode,
+                    ClearMode::Accumulate(_)
+                )
+            })
+        {
+            self.decay_pass = Some(DecayPass::new(display)?);
         }
 
-        for (stage_index, stage) in self.render_chain.iter().enumerate() {
+        let render_graph = RenderGraph::from_stages(&self.render_chain);
+        self.rendered_stage_list = vec![false; self.render_chain.len()];
+
+        for &stage_index in render_graph.get_render_order() {
+            let stage = &self.render_chain[stage_index];
+
+            // Buffers are ordered from the most recent frame to the oldest one, which is
+            // overwritten and then rotated to the front
             if let Some((render_target_pack, _)) = self.render_buffer_list.get(stage_index) {
-                let render_target = &render_target_pack[1];
+                if let Some(render_target_list) = render_target_pack.last() {
+                    let target = if render_target_list.len() > 1 {
+                        RenderTarget::MultiFrameBuffer(
+                            get_output_list(&self.filter_list, stage)
+                                .iter()
+                                .zip(render_target_list)
+                                .collect(),
+                        )
+                    } else {
+                        RenderTarget::FrameBuffer(&render_target_list[0])
+                    };
+
+                    // The oldest buffer starts from the previous frame when the stage draws over it
+                    let decay = match stage.get_draw_options().clear_mode {
+                        ClearMode::Keep => Some(1.0),
+                        ClearMode::Accumulate(decay) => Some(decay),
+                        _ => None,
+                    };
+                    if let (Some(decay), false, true) = (
+                        decay,
+                        stage.is_clear_requested(),
+                        render_target_pack.len() > 1,
+                    ) {
+                        for (previous_buffer, render_target) in
+                            render_target_pack[0].iter().zip(render_target_list)
+                        {
+                            match &self.decay_pass {
+                                Some(decay_pass) => decay_pass.apply(
+                                    display,
+                                    previous_buffer,
+                                    render_target,
+                                    stage.get_buffer_format(),
+                                    decay,
+                                )?,
+                                _ => previous_buffer.as_surface().fill(
+                                    &render_target.as_surface(),
+                                    MagnifySamplerFilter::Nearest,
+                                ),
+                            }
+                        }
+                    }
 
-                self.render_stage(display, stage, RenderTarget::FrameBuffer(render_target))?;
+                    let query = self.render_stage_profiled(display, stage, target)?;
+                    if let (Some(profiler), Some(query)) = (&mut self.profiler, query) {
+                        profiler.push_stage_query(self.render_chain[stage_index].get_name(), query);
+                    }
+                }
             }
 
             if let Some((ref mut render_target_pack, _)) =
                 self.render_buffer_list.get_mut(stage_index)
             {
-                let tmp_buffer = render_target_pack.remove(0);
-                render_target_pack.push(tmp_buffer);
+                render_target_pack.rotate_right(1);
 
-                if texture_with_mipmap_list.contains(stage.get_name()) {
-                    unsafe {
-                        render_target_pack[0].generate_mipmaps();
+                if stage_with_mipmap_list.contains(&stage_index) {
+                    for render_target in &render_target_pack[0] {
+                        unsafe {
+                            render_target.generate_mipmaps();
+                        }
                     }
                 }
             }
+
+            self.rendered_stage_list[stage_index] = true;
+            self.render_chain[stage_index].clear_requested = false;
+        }
+
+        for readback_queue in &mut self.readback_queue_list {
+            if let Some((_, (render_target_pack, _))) = self
+                .render_chain
+                .iter()
+                .zip(&self.render_buffer_list)
+                .find(|(render_stage, _)| {
+                    render_stage.get_name() == readback_queue.get_stage_name()
+                })
+            {
+                readback_queue.push_frame(display, &render_target_pack[0][0])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Allocates one depth buffer per target size of the stages using depth testing.
+    fn update_depth_buffers(&mut self, display: &dyn Facade) -> Result<()> {
+        let mut resolution_list = HashSet::new();
+        for (stage, (_, resolution)) in self.render_chain.iter().zip(&self.render_buffer_list) {
+            if stage.get_draw_options().depth_test.is_some() {
+                resolution_list.insert(*resolution);
+            }
+        }
+        if self.final_stage.get_draw_options().depth_test.is_some() {
+            resolution_list.insert((self.resolution.0 as u32, self.resolution.1 as u32));
+        }
+
+        self.depth_buffer_list
+            .retain(|resolution, _| resolution_list.contains(resolution));
+
+        for resolution in resolution_list {
+            if let Entry::Vacant(entry) = self.depth_buffer_list.entry(resolution) {
+                entry.insert(
+                    DepthRenderBuffer::new(display, DepthFormat::I24, resolution.0, resolution.1)
+                        .context("Failed to create depth buffer")?,
+                );
+            }
         }
 
         Ok(())
@@ -320,21 +650,175 @@ impl ShaderView {
         display: &dyn Facade,
         window_frame: &mut Frame,
     ) -> Result<()> {
-        self.render_stage(
+        let query = self.render_stage_profiled(
             display,
             &self.final_stage,
             RenderTarget::Window(window_frame),
         )?;
+        self.final_stage.clear_requested = false;
+        self.push_final_stage_query(query);
 
         Ok(())
     }
 
+    /// Renders the final stage into a texture owned by the view instead of a window frame,
+    /// which allows rendering with a headless facade.
+    fn render_final_buffer(&mut self, display: &dyn Facade) -> Result<()> {
+        let resolution = (self.resolution.0 as u32, self.resolution.1 as u32);
+
+        let final_buffer = match self.final_buffer.take() {
+            Some(final_buffer) if final_buffer.dimensions() == resolution => final_buffer,
+            _ => {
+                let final_buffer = Texture2d::empty_with_format(
+                    display,
+                    UncompressedFloatFormat::U8U8U8U8,
+                    MipmapsOption::NoMipmap,
+                    resolution.0,
+                    resolution.1,
+                )
+                .context("Failed to create the final rendering buffer")?;
+                final_buffer.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+
+                final_buffer
+            }
+        };
+
+        let render_result = self.render_stage_profiled(
+            display,
+            &self.final_stage,
+            RenderTarget::Offscreen(&final_buffer),
+        );
+
+        self.final_buffer = Some(final_buffer);
+        self.final_stage.clear_requested = false;
+
+        self.push_final_stage_query(render_result?);
+
+        Ok(())
+    }
+
+    fn push_final_stage_query(&mut self, query: Option<TimeElapsedQuery>) {
+        if let (Some(profiler), Some(query)) = (&mut self.profiler, query) {
+            profiler.push_stage_query(self.final_stage.get_name(), query);
+        }
+    }
+
+    pub fn render_final_to_texture(&mut self, display: &dyn Facade) -> Result<RGBAImageData> {
+        self.render_final_buffer(display)?;
+
+        self.final_buffer
+            .as_ref()
+            .context("Final rendering buffer is missing")?
+            .read_to_pixel_buffer()
+            .read_as_texture_2d()
+            .context("Could not read final buffer as a pixel buffer")
+    }
+
+    /// Renders the final stage and reads `region` of it, or all of it when `region` is `None`.
+    ///
+    /// With a `scale` above 1, the whole render chain is rendered again at `scale` times the
+    /// view resolution and `region` is expressed in the scaled pixels. That frame is rendered into
+    /// temporary buffers, cleared beforehand so that feedback and history inputs read transparent
+    /// black, and the live buffers, pending clears, readback queues and profiling stats are left
+    /// as they were.
+    pub fn take_final_screenshot(
+        &mut self,
+        display: &dyn Facade,
+        region: Option<Rect>,
+        scale: usize,
+    ) -> Result<RGBAImageData> {
+        let scale = scale.max(1);
+        if scale == 1 {
+            self.render_final_buffer(display)?;
+
+            let final_buffer = self
+                .final_buffer
+                .as_ref()
+                .context("Final rendering buffer is missing")?;
+            let region = region.unwrap_or_else(|| full_texture_rect(final_buffer));
+
+            return read_texture_region(final_buffer, &region);
+        }
+
+        let resolution = self.resolution;
+        let scaled_resolution = match (
+            resolution.0.checked_mul(scale),
+            resolution.1.checked_mul(scale),
+        ) {
+            (Some(width), Some(height)) => (width, height),
+            _ => anyhow::bail!("Screenshot scale {} is too large", scale),
+        };
+        let scaled_buffer_list = self.build_render_buffer_list(display, scaled_resolution)?;
+        for render_buffer_pack in &scaled_buffer_list {
+            clear_render_buffers(render_buffer_pack);
+        }
+
+        let render_buffer_list =
+            std::mem::replace(&mut self.render_buffer_list, scaled_buffer_list);
+        let rendered_stage_list = std::mem::take(&mut self.rendered_stage_list);
+        let final_buffer = self.final_buffer.take();
+        let readback_queue_list = std::mem::take(&mut self.readback_queue_list);
+        let profiler = self.profiler.take();
+        let clear_requested_list: Vec<bool> = self
+            .render_chain
+            .iter()
+            .chain(Some(&self.final_stage))
+            .map(Stage::is_clear_requested)
+            .collect();
+        self.resolution = scaled_resolution;
+
+        let capture_result = self
+            .render_stages(display)
+            .and_then(|_| self.render_final_buffer(display))
+            .and_then(|_| {
+                let scaled_final_buffer = self
+                    .final_buffer
+                    .as_ref()
+                    .context("Final rendering buffer is missing")?;
+                let region = region.unwrap_or_else(|| full_texture_rect(scaled_final_buffer));
+
+                read_texture_region(scaled_final_buffer, &region)
+            });
+
+        self.resolution = resolution;
+        self.render_buffer_list = render_buffer_list;
+        self.rendered_stage_list = rendered_stage_list;
+        self.final_buffer = final_buffer;
+        self.readback_queue_list = readback_queue_list;
+        self.profiler = profiler;
+        for (stage, clear_requested) in self
+            .render_chain
+            .iter_mut()
+            .chain(Some(&mut self.final_stage))
+            .zip(clear_requested_list)
+        {
+            stage.clear_requested = clear_requested;
+        }
+
+        capture_result
+    }
+
+    pub fn get_final_texture(&self) -> Option<&Texture2d> {
+        self.final_buffer.as_ref()
+    }
+
     pub fn render_stage(
         &self,
         display: &dyn Facade,
         stage: &Stage,
         target: RenderTarget,
     ) -> Result<()> {
+        self.render_stage_profiled(display, stage, target)
+            .map(|_| ())
+    }
+
+    /// Renders a stage, timing it on the GPU when profiling is enabled.
+    fn render_stage_profiled(
+        &self,
+        display: &dyn Facade,
+        stage: &Stage,
+        target: RenderTarget,
+    ) -> Result<Option<TimeElapsedQuery>> {
         let mut render_buffer_list = HashMap::new();
         let mut input_holder = HashMap::new();
 
@@ -356,25 +840,56 @@ impl ShaderView {
                     MagnifySamplerFilter::Linear,
                 ),
             };
+            let sampler_options = stage.get_input_sampler_options(uniform_name);
 
-            let mut render_buffer_for_input = None;
-            for (stage_index, stage) in self.render_chain.iter().enumerate() {
-                if stage.get_name() == input_name {
-                    render_buffer_for_input = Some(stage_index);
-                }
-            }
+            if let Some((render_buffer_index, output_name, frame_offset)) =
+                find_input_stage(&self.render_chain, input_name)
+            {
+                let output_index = match output_name {
+                    Some(output_name) => {
+                        get_output_list(&self.filter_list, &self.render_chain[render_buffer_index])
+                            .iter()
+                            .position(|name| name == output_name)
+                    }
+                    None => Some(0),
+                };
+
+                // Feedback inputs read the previous frame even when their source already rendered
+                let frame_offset = if frame_offset == 0 && stage.is_feedback_input(uniform_name) {
+                    1
+                } else {
+                    frame_offset
+                };
+
+                if let (Some(render_buffer_pack), Some(output_index)) = (
+                    self.render_buffer_list.get(render_buffer_index),
+                    output_index,
+                ) {
+                    let buffer_index =
+                        if self.rendered_stage_list.get(render_buffer_index) == Some(&true) {
+                            frame_offset.min(render_buffer_pack.0.len() - 1)
+                        } else {
+                            // The oldest buffer is about to be overwritten by this frame's render
+                            frame_offset
+                                .saturating_sub(1)
+                                .min(render_buffer_pack.0.len().saturating_sub(2))
+                        };
 
-            if let Some(render_buffer_index) = render_buffer_for_input {
-                if let Some(render_buffer_pack) = self.render_buffer_list.get(render_buffer_index) {
                     render_buffer_list.insert(
                         uniform_name,
-                        (&render_buffer_pack.0[0], Some((down_sampling, up_sampling))),
+                        (
+                            &render_buffer_pack.0[buffer_index][output_index],
+                            Some((down_sampling, up_sampling, sampler_options)),
+                        ),
                     );
                 }
             } else if let Some(uniform_value) = self.uniform_holder.get(input_name) {
                 input_holder.insert(
                     uniform_name,
-                    (uniform_value, Some((down_sampling, up_sampling))),
+                    (
+                        uniform_value,
+                        Some((down_sampling, up_sampling, sampler_options)),
+                    ),
                 );
             }
         }
@@ -383,18 +898,44 @@ impl ShaderView {
             input_holder.insert(uniform_name, (uniform_value, None));
         }
 
+        let mut draw_options = stage.get_draw_options();
+        if stage.is_clear_requested() {
+            draw_options.clear_mode = ClearMode::Default;
+        }
+        let depth_buffer = match draw_options.depth_test {
+            Some(_) => target
+                .get_dimensions()
+                .and_then(|resolution| self.depth_buffer_list.get(&resolution)),
+            None => None,
+        };
+
         let filter_name = stage.get_filter();
         if let Some(filter) = self.filter_list.get(filter_name) {
+            // Timer queries may be unsupported, in which case only CPU timings are kept
+            let query = match self.profiler {
+                Some(_) => TimeElapsedQuery::new(display).ok(),
+                None => None,
+            };
+
             filter.render(
                 display,
                 &input_holder,
                 &render_buffer_list,
                 target,
                 stage.get_filter_mode_params(),
+                &draw_options,
+                depth_buffer,
+                query.as_ref(),
             )?;
+
+            return Ok(query);
         }
 
-        Ok(())
+        Ok(None)
+    }
+
+    pub fn get_render_graph(&self) -> RenderGraph {
+        RenderGraph::from_stages(&self.render_chain)
     }
 
     pub fn stage_index_list(&self) -> HashMap<String, usize> {
@@ -405,6 +946,17 @@ impl ShaderView {
             .collect()
     }
 
+    pub fn get_filter_status(&self, filter_name: &str) -> Option<&FilterStatus> {
+        self.filter_list.get(filter_name).map(Filter::get_status)
+    }
+
+    pub fn filter_status_list(&self) -> HashMap<String, &FilterStatus> {
+        self.filter_list
+            .iter()
+            .map(|(filter_name, filter)| (filter_name.clone(), filter.get_status()))
+            .collect()
+    }
+
     pub fn get_dynamic_resolution(&self) -> bool {
         self.dynamic
     }
@@ -425,51 +977,161 @@ impl ShaderView {
             return Ok(());
         }
 
+        self.create_buffers(display, resolution)
+    }
+
+    /// Replaces the stage buffers only once every buffer at the new resolution was created.
+    fn create_buffers(&mut self, display: &dyn Facade, resolution: (usize, usize)) -> Result<()> {
+        self.render_buffer_list = self.build_render_buffer_list(display, resolution)?;
         self.resolution = resolution;
-        self.render_buffer_list.clear();
-
-        for stage in self.render_chain.iter() {
-            let new_render_buffer_pair = (
-                vec![
-                    Texture2d::empty_with_format(
-                        display,
-                        stage.get_buffer_format(),
-                        MipmapsOption::EmptyMipmaps,
-                        self.resolution.0 as u32,
-                        self.resolution.1 as u32,
-                    )
-                    .context("Failed to create a rendering buffer")?,
-                    Texture2d::empty_with_format(
-                        display,
-                        stage.get_buffer_format(),
-                        MipmapsOption::EmptyMipmaps,
-                        self.resolution.0 as u32,
-                        self.resolution.1 as u32,
-                    )
-                    .context("Failed to create a rendering buffer")?,
-                ],
-                (self.resolution.0 as u32, self.resolution.1 as u32),
-            );
 
-            self.render_buffer_list.push(new_render_buffer_pair);
+        Ok(())
+    }
+
+    fn build_render_buffer_list(
+        &self,
+        display: &dyn Facade,
+        resolution: (usize, usize),
+    ) -> Result<Vec<RenderBufferPack>> {
+        let mut render_buffer_list = Vec::with_capacity(self.render_chain.len());
+
+        for stage_index in 0..self.render_chain.len() {
+            let stage_resolution =
+                compute_stage_resolution(&self.render_chain, stage_index, resolution);
+
+            render_buffer_list.push(create_render_buffers(
+                display,
+                &self.render_chain[stage_index],
+                stage_resolution,
+                get_output_list(&self.filter_list, &self.render_chain[stage_index]).len(),
+            )?);
         }
 
-        Ok(())
+        Ok(render_buffer_list)
+    }
+
+    fn get_stage_texture(&self, stage_name: &str) -> Option<(&Stage, &Texture2d)> {
+        self.render_chain
+            .iter()
+            .zip(&self.render_buffer_list)
+            .find(|(render_stage, _)| render_stage.get_name() == stage_name)
+            .map(|(render_stage, (texture_list, _))| (render_stage, &texture_list[0][0]))
     }
 
     pub fn take_screenshot(&self, stage_name: &str) -> Option<Result<RGBAImageData>> {
-        for (render_stage, (texture_list, _)) in
-            self.render_chain.iter().zip(&self.render_buffer_list)
-        {
-            if render_stage.get_name() == stage_name {
-                return Some(
-                    texture_list[0]
-                        .read_to_pixel_buffer()
-                        .read_as_texture_2d()
-                        .context("Could not read blit texture as a pixel buffer"),
-                );
-            }
+        self.get_stage_texture(stage_name).map(|(_, texture)| {
+            texture
+                .read_to_pixel_buffer()
+                .read_as_texture_2d()
+                .context("Could not read blit texture as a pixel buffer")
+        })
+    }
+
+    /// Reads `region` of a stage buffer, in pixels from its bottom left corner.
+    pub fn take_screenshot_region(
+        &self,
+        stage_name: &str,
+        region: &Rect,
+    ) -> Option<Result<RGBAImageData>> {
+        self.get_stage_texture(stage_name)
+            .map(|(_, texture)| read_texture_region(texture, region))
+    }
+
+    /// Reads a stage buffer without losing the precision of `F16` and `F32` stages.
+    pub fn take_float_screenshot(&self, stage_name: &str) -> Option<Result<RGBAFloatImageData>> {
+        self.get_stage_texture(stage_name).map(|(_, texture)| {
+            let image = texture
+                .main_level()
+                .first_layer()
+                .into_image(None)
+                .context("Could not access stage texture image")?;
+
+            Ok(image.raw_read(&full_texture_rect(texture)))
+        })
+    }
+
+    /// Alpha handling, data encoding and float precision of an input texture, applied from the
+    /// next upload on.
+    pub fn set_input_texture_options(&mut self, input_name: &str, options: TextureOptions) {
+        self.input_texture_options_list
+            .insert(input_name.to_owned(), options);
+    }
+
+    pub fn get_input_texture_options(&self, input_name: &str) -> TextureOptions {
+        self.input_texture_options_list
+            .get(input_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Profiling costs nothing while disabled, disabling it drops the collected samples.
+    pub fn set_profiling_enabled(&mut self, enabled: bool) {
+        if enabled != self.profiler.is_some() {
+            self.profiler = if enabled {
+                Some(Profiler::new(profiler::DEFAULT_SAMPLE_COUNT))
+            } else {
+                None
+            };
         }
-        None
+    }
+
+    pub fn is_profiling_enabled(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Timings averaged over the last frames, `None` while profiling is disabled.
+    pub fn get_frame_stats(&self) -> Option<FrameStats> {
+        self.profiler.as_ref().map(Profiler::get_frame_stats)
+    }
+
+    /// Starts reading a stage back every frame without stalling the rendering, replacing any
+    /// queue already reading the same stage.
+    pub fn start_readback(&mut self, readback_queue: ReadbackQueue) {
+        self.stop_readback(readback_queue.get_stage_name());
+        self.readback_queue_list.push(readback_queue);
+    }
+
+    pub fn stop_readback(&mut self, stage_name: &str) -> Option<ReadbackQueue> {
+        let queue_index = self
+            .readback_queue_list
+            .iter()
+            .position(|readback_queue| readback_queue.get_stage_name() == stage_name)?;
+
+        Some(self.readback_queue_list.remove(queue_index))
+    }
+
+    pub fn get_readback_queue_mut(&mut self, stage_name: &str) -> Option<&mut ReadbackQueue> {
+        self.readback_queue_list
+            .iter_mut()
+            .find(|readback_queue| readback_queue.get_stage_name() == stage_name)
+    }
+
+    /// Oldest completed frame read back from a stage, if any.
+    pub fn poll_readback(&mut self, stage_name: &str) -> Option<ReadbackFrame> {
+        self.get_readback_queue_mut(stage_name)?.poll()
+    }
+
+    /// Saves a stage buffer to `path`, by default losslessly for the stage precision.
+    pub fn export_screenshot(
+        &self,
+        stage_name: &str,
+        path: &Path,
+        options: Option<ExportOptions>,
+    ) -> Option<Result<()>> {
+        let (stage, _) = self.get_stage_texture(stage_name)?;
+        let options =
+            options.unwrap_or_else(|| ExportOptions::for_buffer_format(stage.get_buffer_format()));
+
+        Some(match options.format {
+            ExportFormat::Png8 => self
+                .take_screenshot(stage_name)?
+                .and_then(|image_data| export::save_png8(path, &image_data, options.srgb_encode)),
+            ExportFormat::Png16 => self
+                .take_float_screenshot(stage_name)?
+                .and_then(|image_data| export::save_png16(path, &image_data, options.srgb_encode)),
+            ExportFormat::Pfm => self
+                .take_float_screenshot(stage_name)?
+                .and_then(|image_data| export::save_pfm(path, &image_data)),
+        })
     }
 }
@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use wvr_data::shader::Shader;
+
+/// GLSL helpers shipped with the renderer, as (file name in the libs path, source).
+///
+/// Filters list them among their shader files like any other file. When a filter folder or
+/// the libs folder holds a file of the same name that file is used, otherwise the shipped
+/// source is, so the helpers need no installation.
+pub const LIB_FILE_LIST: &[(&str, &str)] =
+    &[("wvr_buffer.glsl", include_str!("../libs/wvr_buffer.glsl"))];
+
+/// Source of a shipped GLSL helper, which never changes.
+struct LibShader(&'static str);
+
+impl Shader for LibShader {
+    fn get_text(&self) -> &str {
+        self.0
+    }
+
+    fn check_changes(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn update(&mut self) {}
+}
+
+/// The shipped GLSL helper named `file_name`, if any.
+pub fn get_lib_shader(file_name: &str) -> Option<Box<dyn Shader>> {
+    LIB_FILE_LIST
+        .iter()
+        .find(|(lib_file_name, _)| *lib_file_name == file_name)
+        .map(|(_, source)| Box::new(LibShader(source)) as Box<dyn Shader>)
+}
+
+/// Writes the shipped GLSL helpers into `libs_path`, usually `wvr_data::get_libs_path()`, to
+/// read or customise them. Files already up to date are left untouched, others are
+/// overwritten.
+pub fn install_libs(libs_path: &Path) -> Result<()> {
+    fs::create_dir_all(libs_path).context("Failed to create libs folder")?;
+
+    for (file_name, source) in LIB_FILE_LIST {
+        let file_path = libs_path.join(file_name);
+
+        if fs::read_to_string(&file_path).ok().as_deref() != Some(*source) {
+            fs::write(&file_path, source)
+                .with_context(|| format!("Failed to install {:?}", file_path))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provides_shipped_helpers() {
+        let shader = get_lib_shader("wvr_buffer.glsl").unwrap();
+
+        assert!(shader.get_text().contains("wvr_buffer_fetch"));
+        assert!(get_lib_shader("missing.glsl").is_none());
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use glium::backend::Facade;
+
+use wvr_data::types::{DataHolder, InputProvider};
+
+use crate::export::save_png8;
+use crate::{RGBAImageData, ShaderView};
+
+/// Renders a project frame by frame at a fixed frame rate, independently of wall-clock time,
+/// and saves every final frame as `<prefix><frame index>.png`.
+///
+/// Time, beat and frame count only depend on the frame index, so rendering the same range
+/// twice gives the same images as long as the input providers are deterministic too.
+pub struct OfflineRenderer {
+    output_folder: PathBuf,
+    file_prefix: String,
+
+    frame_rate: f64,
+    bpm: f64,
+
+    warm_up_frame_count: Option<usize>,
+    next_frame_index: usize,
+    last_rendered_frame_index: Option<usize>,
+}
+
+impl OfflineRenderer {
+    /// Frame rate and tempo must be positive, any other value would make frame times invalid.
+    pub fn new(output_folder: &Path, frame_rate: f64, bpm: f64) -> Result<Self> {
+        if !frame_rate.is_finite() || frame_rate <= 0.0 {
+            anyhow::bail!("Invalid frame rate {}", frame_rate);
+        }
+        if !bpm.is_finite() || bpm <= 0.0 {
+            anyhow::bail!("Invalid tempo {} bpm", bpm);
+        }
+
+        Ok(Self {
+            output_folder: output_folder.to_path_buf(),
+            file_prefix: "frame_".to_owned(),
+
+            frame_rate,
+            bpm,
+
+            warm_up_frame_count: None,
+            next_frame_index: 0,
+            last_rendered_frame_index: None,
+        })
+    }
+
+    pub fn set_file_prefix(&mut self, file_prefix: &str) {
+        self.file_prefix = file_prefix.to_owned();
+    }
+
+    /// Number of frames rendered without being saved before the first saved frame when
+    /// resuming, so that feedback and history stages are filled again.
+    /// `None` replays every frame from the start of the sequence.
+    pub fn set_warm_up_frame_count(&mut self, warm_up_frame_count: Option<usize>) {
+        self.warm_up_frame_count = warm_up_frame_count;
+    }
+
+    pub fn get_frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    pub fn get_bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    pub fn get_next_frame_index(&self) -> usize {
+        self.next_frame_index
+    }
+
+    pub fn get_frame_time(&self, frame_index: usize) -> f64 {
+        frame_index as f64 / self.frame_rate
+    }
+
+    pub fn get_frame_beat(&self, frame_index: usize) -> f64 {
+        self.get_frame_time(frame_index) * self.bpm / 60.0
+    }
+
+    pub fn get_frame_path(&self, frame_index: usize) -> PathBuf {
+        self.output_folder
+            .join(format!("{}{:06}.png", self.file_prefix, frame_index))
+    }
+
+    /// Index of the first frame of the sequence that has not been saved yet.
+    pub fn find_resume_frame_index(&self) -> usize {
+        let mut frame_index = 0;
+        while self.get_frame_path(frame_index).exists() {
+            frame_index += 1;
+        }
+
+        frame_index
+    }
+
+    /// Makes the next call to `render_frames` start at `frame_index`.
+    pub fn resume_from(&mut self, frame_index: usize) {
+        self.next_frame_index = frame_index;
+    }
+
+    /// Index the rendering restarts from, with cleared buffers, when the next frame does not
+    /// follow the last rendered one. `None` when the rendering simply goes on.
+    fn get_warm_up_start_index(&self) -> Option<usize> {
+        let first_frame_index = self.next_frame_index;
+        if first_frame_index > 0 && self.last_rendered_frame_index == Some(first_frame_index - 1) {
+            return None;
+        }
+
+        Some(match self.warm_up_frame_count {
+            Some(warm_up_frame_count) => first_frame_index.saturating_sub(warm_up_frame_count),
+            None => 0,
+        })
+    }
+
+    /// Renders and saves `frame_count` frames starting from the next frame index. Unless the
+    /// previous frame was the last one rendered, the view buffers are cleared first and
+    /// warm-up frames are rendered.
+    pub fn render_frames(
+        &mut self,
+        shader_view: &mut ShaderView,
+        display: &dyn Facade,
+        env_variable_list: &HashMap<String, DataHolder>,
+        uniform_sources: &mut HashMap<String, Box<dyn InputProvider>>,
+        frame_count: usize,
+    ) -> Result<Vec<PathBuf>> {
+        let first_frame_index = self.next_frame_index;
+        let warm_up_start_index = match self.get_warm_up_start_index() {
+            Some(warm_up_start_index) => {
+                shader_view.reset_buffers();
+                self.last_rendered_frame_index = None;
+
+                warm_up_start_index
+            }
+            None => first_frame_index,
+        };
+
+        for frame_index in warm_up_start_index..first_frame_index {
+            self.render_frame(
+                shader_view,
+                display,
+                env_variable_list,
+                uniform_sources,
+                frame_index,
+            )?;
+            self.last_rendered_frame_index = Some(frame_index);
+        }
+
+        let mut frame_path_list = Vec::new();
+        for frame_index in first_frame_index..first_frame_index + frame_count {
+            let image_data = self.render_frame(
+                shader_view,
+                display,
+                env_variable_list,
+                uniform_sources,
+                frame_index,
+            )?;
+            self.last_rendered_frame_index = Some(frame_index);
+
+            let frame_path = self.get_frame_path(frame_index);
+            save_png8(&frame_path, &image_data, false)
+                .with_context(|| format!("Failed to save frame {}", frame_index))?;
+            frame_path_list.push(frame_path);
+
+            self.next_frame_index = frame_index + 1;
+        }
+
+        Ok(frame_path_list)
+    }
+
+    fn render_frame(
+        &self,
+        shader_view: &mut ShaderView,
+        display: &dyn Facade,
+        env_variable_list: &HashMap<String, DataHolder>,
+        uniform_sources: &mut HashMap<String, Box<dyn InputProvider>>,
+        frame_index: usize,
+    ) -> Result<RGBAImageData> {
+        shader_view.update(
+            display,
+            env_variable_list,
+            uniform_sources,
+            self.get_frame_time(frame_index),
+            self.get_frame_beat(frame_index),
+            frame_index,
+        )?;
+        shader_view.render_stages(display)?;
+
+        shader_view.render_final_to_texture(display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn build_renderer(output_folder: &Path) -> OfflineRenderer {
+        OfflineRenderer::new(output_folder, 25.0, 120.0).unwrap()
+    }
+
+    #[test]
+    fn steps_time_and_beat_with_the_frame_index() {
+        let renderer = build_renderer(Path::new("frames"));
+
+        assert_eq!(renderer.get_frame_time(0), 0.0);
+        assert_eq!(renderer.get_frame_time(50), 2.0);
+        assert_eq!(renderer.get_frame_beat(50), 4.0);
+        assert_eq!(renderer.get_frame_beat(25 * 60), 120.0);
+    }
+
+    #[test]
+    fn names_frames_with_padded_indices() {
+        let mut renderer = build_renderer(Path::new("frames"));
+        assert_eq!(
+            renderer.get_frame_path(42),
+            Path::new("frames").join("frame_000042.png")
+        );
+
+        renderer.set_file_prefix("take_2_");
+        assert_eq!(
+            renderer.get_frame_path(1234567),
+            Path::new("frames").join("take_2_1234567.png")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        assert!(OfflineRenderer::new(Path::new("frames"), 0.0, 120.0).is_err());
+        assert!(OfflineRenderer::new(Path::new("frames"), f64::NAN, 120.0).is_err());
+        assert!(OfflineRenderer::new(Path::new("frames"), 25.0, -1.0).is_err());
+        assert!(OfflineRenderer::new(Path::new("frames"), 25.0, f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn restarts_from_the_first_frame() {
+        let mut renderer = build_renderer(Path::new("frames"));
+        assert_eq!(renderer.get_warm_up_start_index(), Some(0));
+
+        renderer.last_rendered_frame_index = Some(10);
+        assert_eq!(renderer.get_warm_up_start_index(), Some(0));
+    }
+
+    #[test]
+    fn warms_up_before_resumed_frames() {
+        let mut renderer = build_renderer(Path::new("frames"));
+        renderer.resume_from(100);
+        assert_eq!(renderer.get_warm_up_start_index(), Some(0));
+
+        renderer.set_warm_up_frame_count(Some(30));
+        assert_eq!(renderer.get_warm_up_start_index(), Some(70));
+
+        renderer.set_warm_up_frame_count(Some(300));
+        assert_eq!(renderer.get_warm_up_start_index(), Some(0));
+    }
+
+    #[test]
+    fn goes_on_after_the_last_rendered_frame() {
+        let mut renderer = build_renderer(Path::new("frames"));
+        renderer.set_warm_up_frame_count(Some(30));
+        renderer.resume_from(100);
+
+        renderer.last_rendered_frame_index = Some(99);
+        assert_eq!(renderer.get_warm_up_start_index(), None);
+
+        renderer.last_rendered_frame_index = Some(98);
+        assert_eq!(renderer.get_warm_up_start_index(), Some(70));
+    }
+
+    #[test]
+    fn resumes_after_the_saved_frames() {
+        let output_folder =
+            std::env::temp_dir().join(format!("wvr_offline_test_{}", std::process::id()));
+        fs::create_dir_all(&output_folder).unwrap();
+
+        let renderer = build_renderer(&output_folder);
+        let resume_frame_index = renderer.find_resume_frame_index();
+        for frame_index in 0..3 {
+            fs::write(renderer.get_frame_path(frame_index), b"").unwrap();
+        }
+        let saved_resume_frame_index = renderer.find_resume_frame_index();
+        fs::remove_dir_all(&output_folder).unwrap();
+
+        assert_eq!(resume_frame_index, 0);
+        assert_eq!(saved_resume_frame_index, 3);
+    }
+}
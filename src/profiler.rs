@@ -0,0 +1,111 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use glium::draw_parameters::TimeElapsedQuery;
+
+pub const DEFAULT_SAMPLE_COUNT: usize = 60;
+
+/// Timings averaged over the last frames.
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    /// GPU time spent drawing each stage, the final stage included.
+    pub stage_gpu_time_list: HashMap<String, Duration>,
+    pub total_gpu_time: Duration,
+    /// CPU time spent converting and uploading input provider values in `update`.
+    pub uniform_upload_time: Duration,
+}
+
+/// Collects GPU timer queries and CPU timings. Query results are only read once the GPU
+/// reports them as available, a few frames after they were issued, so profiling never
+/// waits on the GPU.
+pub struct Profiler {
+    sample_count: usize,
+
+    pending_query_list: VecDeque<(String, TimeElapsedQuery)>,
+    stage_sample_list: HashMap<String, VecDeque<Duration>>,
+    uniform_upload_sample_list: VecDeque<Duration>,
+}
+
+fn push_sample(sample_list: &mut VecDeque<Duration>, sample: Duration, sample_count: usize) {
+    sample_list.push_back(sample);
+    while sample_list.len() > sample_count {
+        sample_list.pop_front();
+    }
+}
+
+fn average(sample_list: &VecDeque<Duration>) -> Duration {
+    if sample_list.is_empty() {
+        return Duration::default();
+    }
+
+    sample_list.iter().sum::<Duration>() / sample_list.len() as u32
+}
+
+impl Profiler {
+    pub fn new(sample_count: usize) -> Self {
+        Self {
+            sample_count: sample_count.max(1),
+
+            pending_query_list: VecDeque::new(),
+            stage_sample_list: HashMap::new(),
+            uniform_upload_sample_list: VecDeque::new(),
+        }
+    }
+
+    pub fn get_sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    pub fn push_stage_query(&mut self, stage_name: &str, query: TimeElapsedQuery) {
+        self.pending_query_list
+            .push_back((stage_name.to_owned(), query));
+    }
+
+    pub fn push_uniform_upload_time(&mut self, upload_time: Duration) {
+        push_sample(
+            &mut self.uniform_upload_sample_list,
+            upload_time,
+            self.sample_count,
+        );
+    }
+
+    /// Reads the results of the queries that are available, in the order they were issued.
+    pub fn collect_queries(&mut self) {
+        while let Some((_, query)) = self.pending_query_list.front() {
+            if !query.is_ready() {
+                break;
+            }
+
+            if let Some((stage_name, query)) = self.pending_query_list.pop_front() {
+                let sample_list = self.stage_sample_list.entry(stage_name).or_default();
+                push_sample(
+                    sample_list,
+                    Duration::from_nanos(query.get().into()),
+                    self.sample_count,
+                );
+            }
+        }
+    }
+
+    /// Forgets the timings of stages that were removed or renamed.
+    pub fn retain_stages(&mut self, stage_name_list: &HashSet<&str>) {
+        self.pending_query_list
+            .retain(|(stage_name, _)| stage_name_list.contains(stage_name.as_str()));
+        self.stage_sample_list
+            .retain(|stage_name, _| stage_name_list.contains(stage_name.as_str()));
+    }
+
+    pub fn get_frame_stats(&self) -> FrameStats {
+        let stage_gpu_time_list: HashMap<String, Duration> = self
+            .stage_sample_list
+            .iter()
+            .map(|(stage_name, sample_list)| (stage_name.clone(), average(sample_list)))
+            .collect();
+
+        FrameStats {
+            total_gpu_time: stage_gpu_time_list.values().sum(),
+            stage_gpu_time_list,
+            uniform_upload_time: average(&self.uniform_upload_sample_list),
+        }
+    }
+}
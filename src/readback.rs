@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+
+use glium::backend::Facade;
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::Texture2d;
+use glium::Rect;
+
+use crate::RGBAImageData;
+
+type RGBAPixelBuffer = PixelBuffer<(u8, u8, u8, u8)>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReadbackDropPolicy {
+    /// Discards the oldest completed frame to make room for the new one.
+    DropOldest,
+    /// Discards the new frame, keeping the frames already waiting.
+    DropNewest,
+}
+
+pub struct ReadbackFrame {
+    pub frame_index: usize,
+    pub image_data: RGBAImageData,
+}
+
+/// Non-blocking readback of a stage buffer, one pixel buffer read issued per rendered frame.
+///
+/// A read is only mapped `latency` frames after being issued, by which time the GPU has
+/// usually finished it, so rendering does not wait on the transfer. At most `capacity`
+/// completed frames wait for the host, extra frames are dropped following `drop_policy`.
+pub struct ReadbackQueue {
+    stage_name: String,
+    latency: usize,
+    capacity: usize,
+    drop_policy: ReadbackDropPolicy,
+
+    next_frame_index: usize,
+    dropped_frame_count: usize,
+
+    pending_list: VecDeque<(usize, (u32, u32), RGBAPixelBuffer)>,
+    completed_list: VecDeque<ReadbackFrame>,
+    free_buffer_list: Vec<RGBAPixelBuffer>,
+}
+
+impl ReadbackQueue {
+    pub fn new(
+        stage_name: &str,
+        latency: usize,
+        capacity: usize,
+        drop_policy: ReadbackDropPolicy,
+    ) -> Self {
+        Self {
+            stage_name: stage_name.to_owned(),
+            latency,
+            capacity: capacity.max(1),
+            drop_policy,
+
+            next_frame_index: 0,
+            dropped_frame_count: 0,
+
+            pending_list: VecDeque::new(),
+            completed_list: VecDeque::new(),
+            free_buffer_list: Vec::new(),
+        }
+    }
+
+    pub fn get_stage_name(&self) -> &String {
+        &self.stage_name
+    }
+
+    pub fn get_latency(&self) -> usize {
+        self.latency
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_drop_policy(&self) -> ReadbackDropPolicy {
+        self.drop_policy
+    }
+
+    pub fn get_dropped_frame_count(&self) -> usize {
+        self.dropped_frame_count
+    }
+
+    pub fn get_pending_count(&self) -> usize {
+        self.pending_list.len()
+    }
+
+    pub fn get_completed_count(&self) -> usize {
+        self.completed_list.len()
+    }
+
+    /// Collects the reads that are old enough, then issues a read of `texture`.
+    pub fn push_frame(&mut self, display: &dyn Facade, texture: &Texture2d) -> Result<()> {
+        while self.pending_list.len() > self.latency {
+            self.complete_oldest()?;
+        }
+
+        let (width, height) = texture.dimensions();
+        let pixel_count = width as usize * height as usize;
+
+        // Buffers left over from a previous resolution are released
+        self.free_buffer_list
+            .retain(|pixel_buffer| pixel_buffer.len() == pixel_count);
+        let pixel_buffer = match self.free_buffer_list.pop() {
+            Some(pixel_buffer) => pixel_buffer,
+            None => PixelBuffer::new_empty(display, pixel_count),
+        };
+
+        texture
+            .main_level()
+            .first_layer()
+            .into_image(None)
+            .context("Could not access stage texture image")?
+            .raw_read_to_pixel_buffer(
+                &Rect {
+                    left: 0,
+                    bottom: 0,
+                    width,
+                    height,
+                },
+                &pixel_buffer,
+            );
+
+        self.pending_list
+            .push_back((self.next_frame_index, (width, height), pixel_buffer));
+        self.next_frame_index += 1;
+
+        Ok(())
+    }
+
+    /// Waits for every read in flight, for example before stopping a recording.
+    pub fn flush(&mut self) -> Result<()> {
+        while !self.pending_list.is_empty() {
+            self.complete_oldest()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn poll(&mut self) -> Option<ReadbackFrame> {
+        self.completed_list.pop_front()
+    }
+
+    fn complete_oldest(&mut self) -> Result<()> {
+        let (frame_index, (width, height), pixel_buffer) = match self.pending_list.pop_front() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let data = pixel_buffer
+            .read()
+            .context("Could not read stage pixel buffer")?;
+        self.free_buffer_list.push(pixel_buffer);
+
+        if self.completed_list.len() >= self.capacity {
+            self.dropped_frame_count += 1;
+
+            match self.drop_policy {
+                ReadbackDropPolicy::DropOldest => {
+                    self.completed_list.pop_front();
+                }
+                ReadbackDropPolicy::DropNewest => return Ok(()),
+            }
+        }
+
+        self.completed_list.push_back(ReadbackFrame {
+            frame_index,
+            image_data: RGBAImageData {
+                data,
+                width,
+                height,
+            },
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,430 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+
+use glium::backend::Facade;
+
+use crate::{RGBAImageData, ShaderView};
+
+/// Both formats are YUV4MPEG2 streams, whose header describing the resolution, frame rate
+/// and pixel format is written when the recorder is created. Colors are converted to BT.601
+/// limited range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RawPixelFormat {
+    /// 8 bits 4:4:4 frames along with a full range alpha plane (`C444alpha`), which keeps the
+    /// transparency of RGBA frames.
+    Yuva444,
+    /// 8 bits 4:2:0 frames (`C420jpeg`).
+    Yuv420,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordSource {
+    Stage(String),
+    Final,
+}
+
+/// Writes rendered frames to `writer` at a fixed frame rate, for example into the standard
+/// input of ffmpeg.
+///
+/// Frames are timed from the first recorded frame: a frame is repeated when the rendering
+/// falls behind the frame rate and skipped when it runs ahead.
+pub struct FrameRecorder<W: Write> {
+    writer: W,
+    source: RecordSource,
+    pixel_format: RawPixelFormat,
+    frame_rate: f64,
+    resolution: (u32, u32),
+
+    start_time: Option<f64>,
+    next_frame_index: usize,
+}
+
+impl<W: Write> FrameRecorder<W> {
+    /// Every recorded frame must have the given resolution, usually the one of the view or of
+    /// the recorded stage.
+    pub fn new(
+        writer: W,
+        source: RecordSource,
+        pixel_format: RawPixelFormat,
+        frame_rate: f64,
+        resolution: (u32, u32),
+    ) -> Result<Self> {
+        if !frame_rate.is_finite() || frame_rate <= 0.0 {
+            anyhow::bail!("Invalid recording frame rate {}", frame_rate);
+        }
+
+        let mut recorder = Self {
+            writer,
+            source,
+            pixel_format,
+            frame_rate,
+            resolution,
+
+            start_time: None,
+            next_frame_index: 0,
+        };
+        recorder.write_header()?;
+
+        Ok(recorder)
+    }
+
+    pub fn get_source(&self) -> &RecordSource {
+        &self.source
+    }
+
+    pub fn get_pixel_format(&self) -> RawPixelFormat {
+        self.pixel_format
+    }
+
+    pub fn get_frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    pub fn get_resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    pub fn get_written_frame_count(&self) -> usize {
+        self.next_frame_index
+    }
+
+    /// Input options that let ffmpeg read the stream from its standard input.
+    pub fn get_ffmpeg_input_arguments(&self) -> Vec<String> {
+        vec![
+            "-f".to_owned(),
+            "yuv4mpegpipe".to_owned(),
+            "-i".to_owned(),
+            "-".to_owned(),
+        ]
+    }
+
+    /// Reads the recorded source from `shader_view` if a frame is due at `time`, in seconds,
+    /// and returns the number of frames written.
+    pub fn record(
+        &mut self,
+        shader_view: &mut ShaderView,
+        display: &dyn Facade,
+        time: f64,
+    ) -> Result<usize> {
+        if self.get_due_frame_count(time) == 0 {
+            return Ok(0);
+        }
+
+        let image_data = match &self.source {
+            RecordSource::Stage(stage_name) => shader_view
+                .take_screenshot(stage_name)
+                .with_context(|| format!("No stage named {} to record", stage_name))??,
+            RecordSource::Final => shader_view.take_final_screenshot(display, None, 1)?,
+        };
+
+        self.push_frame(&image_data, time)
+    }
+
+    /// Writes a frame read elsewhere, for example from a readback queue, as the frame
+    /// shown at `time`, in seconds. Returns the number of frames written.
+    pub fn push_frame(&mut self, image_data: &RGBAImageData, time: f64) -> Result<usize> {
+        let frame_count = self.get_due_frame_count(time);
+        if frame_count == 0 {
+            return Ok(0);
+        }
+
+        let resolution = (image_data.width, image_data.height);
+        if resolution != self.resolution {
+            anyhow::bail!(
+                "Frame resolution {}x{} does not match the {}x{} stream",
+                resolution.0,
+                resolution.1,
+                self.resolution.0,
+                self.resolution.1
+            );
+        }
+
+        if self.start_time.is_none() {
+            self.start_time = Some(time);
+        }
+
+        let frame_data = match self.pixel_format {
+            RawPixelFormat::Yuva444 => yuva444_top_down(image_data),
+            RawPixelFormat::Yuv420 => yuv420_top_down(image_data),
+        };
+
+        for _ in 0..frame_count {
+            self.writer
+                .write_all(b"FRAME\n")
+                .context("Failed to write frame header")?;
+
+            self.writer
+                .write_all(&frame_data)
+                .context("Failed to write frame")?;
+        }
+        self.next_frame_index += frame_count;
+
+        Ok(frame_count)
+    }
+
+    /// Flushes the stream and gives the writer back, which closes pipes when it is dropped.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush().context("Failed to flush recording")?;
+
+        Ok(self.writer)
+    }
+
+    fn get_due_frame_count(&self, time: f64) -> usize {
+        let frame_index = match self.start_time {
+            Some(start_time) => ((time - start_time) * self.frame_rate).max(0.0) as usize,
+            None => 0,
+        };
+
+        (frame_index + 1).saturating_sub(self.next_frame_index)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let colorspace = match self.pixel_format {
+            RawPixelFormat::Yuva444 => "444alpha",
+            RawPixelFormat::Yuv420 => "420jpeg",
+        };
+
+        writeln!(
+            self.writer,
+            "YUV4MPEG2 W{} H{} F{}:1000 Ip A1:1 C{}",
+            self.resolution.0,
+            self.resolution.1,
+            (self.frame_rate * 1000.0).round() as u64,
+            colorspace
+        )
+        .context("Failed to write stream header")
+    }
+}
+
+fn get_luma(r: f32, g: f32, b: f32) -> u8 {
+    (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8
+}
+
+fn get_chroma(r: f32, g: f32, b: f32) -> (u8, u8) {
+    (
+        (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8,
+        (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8,
+    )
+}
+
+/// Texture rows start at the bottom, video frames at the top.
+fn yuva444_top_down(image_data: &RGBAImageData) -> Vec<u8> {
+    let plane_size = image_data.data.len();
+    let mut frame_data = vec![0; plane_size * 4];
+
+    for (texel_index, (r, g, b, a)) in image_data
+        .data
+        .chunks(image_data.width as usize)
+        .rev()
+        .flatten()
+        .enumerate()
+    {
+        let (r, g, b) = (*r as f32, *g as f32, *b as f32);
+        let (u, v) = get_chroma(r, g, b);
+
+        frame_data[texel_index] = get_luma(r, g, b);
+        frame_data[plane_size + texel_index] = u;
+        frame_data[plane_size * 2 + texel_index] = v;
+        frame_data[plane_size * 3 + texel_index] = *a;
+    }
+
+    frame_data
+}
+
+fn yuv420_top_down(image_data: &RGBAImageData) -> Vec<u8> {
+    let width = image_data.width as usize;
+    let height = image_data.height as usize;
+    let chroma_width = width.div_ceil(2);
+    let chroma_height = height.div_ceil(2);
+
+    let get_pixel = |x: usize, y: usize| {
+        let (r, g, b, _) =
+            image_data.data[(height - 1 - y.min(height - 1)) * width + x.min(width - 1)];
+        (r as f32, g as f32, b as f32)
+    };
+
+    let mut frame_data = Vec::with_capacity(width * height + 2 * chroma_width * chroma_height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = get_pixel(x, y);
+            frame_data.push(get_luma(r, g, b));
+        }
+    }
+
+    let mut v_plane = Vec::with_capacity(chroma_width * chroma_height);
+    for y in 0..chroma_height {
+        for x in 0..chroma_width {
+            let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+            for (offset_x, offset_y) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let pixel = get_pixel(x * 2 + offset_x, y * 2 + offset_y);
+                r += pixel.0 / 4.0;
+                g += pixel.1 / 4.0;
+                b += pixel.2 / 4.0;
+            }
+
+            let (u, v) = get_chroma(r, g, b);
+            frame_data.push(u);
+            v_plane.push(v);
+        }
+    }
+    frame_data.extend(v_plane);
+
+    frame_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: (u8, u8, u8, u8) = (0, 0, 0, 255);
+    const WHITE: (u8, u8, u8, u8) = (255, 255, 255, 255);
+    const RED: (u8, u8, u8, u8) = (255, 0, 0, 255);
+
+    fn build_image(
+        width: u32,
+        height: u32,
+        get_pixel: impl Fn(u32, u32) -> (u8, u8, u8, u8),
+    ) -> RGBAImageData {
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                data.push(get_pixel(x, y));
+            }
+        }
+
+        RGBAImageData {
+            data,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn converts_to_yuva_planes() {
+        let image_data = build_image(2, 1, |x, _| if x == 0 { RED } else { (255, 255, 255, 64) });
+
+        assert_eq!(
+            yuva444_top_down(&image_data),
+            vec![82, 235, 90, 128, 240, 128, 255, 64]
+        );
+    }
+
+    #[test]
+    fn flips_yuva_rows() {
+        let image_data = build_image(1, 2, |_, y| if y == 0 { BLACK } else { WHITE });
+
+        assert_eq!(
+            yuva444_top_down(&image_data),
+            vec![235, 16, 128, 128, 128, 128, 255, 255]
+        );
+    }
+
+    #[test]
+    fn converts_to_limited_range_yuv() {
+        let image_data = build_image(2, 2, |_, _| WHITE);
+        assert_eq!(
+            yuv420_top_down(&image_data),
+            vec![235, 235, 235, 235, 128, 128]
+        );
+
+        let image_data = build_image(2, 2, |_, _| BLACK);
+        assert_eq!(yuv420_top_down(&image_data), vec![16, 16, 16, 16, 128, 128]);
+    }
+
+    #[test]
+    fn flips_yuv_rows() {
+        let image_data = build_image(1, 2, |_, y| if y == 0 { BLACK } else { WHITE });
+
+        assert_eq!(&yuv420_top_down(&image_data)[..2], &[235, 16]);
+    }
+
+    #[test]
+    fn converts_odd_size_frames() {
+        let image_data = build_image(1, 1, |_, _| WHITE);
+        assert_eq!(yuv420_top_down(&image_data), vec![235, 128, 128]);
+
+        let image_data = build_image(3, 3, |x, _| if x == 2 { RED } else { BLACK });
+        let frame_data = yuv420_top_down(&image_data);
+
+        assert_eq!(frame_data.len(), 3 * 3 + 2 * 2 * 2);
+        assert_eq!(&frame_data[..3], &[16, 16, 82]);
+        assert_eq!(&frame_data[9..13], &[128, 90, 128, 90]);
+        assert_eq!(&frame_data[13..], &[128, 240, 128, 240]);
+    }
+
+    #[test]
+    fn writes_yuv_stream_header_on_creation() {
+        let recorder = FrameRecorder::new(
+            Vec::new(),
+            RecordSource::Final,
+            RawPixelFormat::Yuv420,
+            30.0,
+            (3, 3),
+        )
+        .unwrap();
+
+        assert_eq!(
+            recorder.finish().unwrap(),
+            b"YUV4MPEG2 W3 H3 F30000:1000 Ip A1:1 C420jpeg\n"
+        );
+    }
+
+    #[test]
+    fn repeats_frames_to_keep_the_frame_rate() {
+        let mut recorder = FrameRecorder::new(
+            Vec::new(),
+            RecordSource::Final,
+            RawPixelFormat::Yuva444,
+            10.0,
+            (1, 1),
+        )
+        .unwrap();
+        let image_data = build_image(1, 1, |_, _| RED);
+
+        assert_eq!(recorder.push_frame(&image_data, 1.0).unwrap(), 1);
+        assert_eq!(recorder.push_frame(&image_data, 1.05).unwrap(), 0);
+        assert_eq!(recorder.push_frame(&image_data, 1.25).unwrap(), 2);
+        assert_eq!(recorder.get_written_frame_count(), 3);
+        assert_eq!(
+            recorder.finish().unwrap(),
+            [
+                b"YUV4MPEG2 W1 H1 F10000:1000 Ip A1:1 C444alpha\n".to_vec(),
+                b"FRAME\n\x52\x5a\xf0\xff".repeat(3)
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_frames() {
+        let mut recorder = FrameRecorder::new(
+            Vec::new(),
+            RecordSource::Final,
+            RawPixelFormat::Yuv420,
+            30.0,
+            (2, 2),
+        )
+        .unwrap();
+
+        assert!(recorder
+            .push_frame(&build_image(3, 3, |_, _| BLACK), 0.0)
+            .is_err());
+        assert_eq!(recorder.get_written_frame_count(), 0);
+    }
+
+    #[test]
+    fn rejects_invalid_frame_rates() {
+        for frame_rate in &[0.0, -30.0, f64::NAN, f64::INFINITY] {
+            assert!(FrameRecorder::new(
+                Vec::new(),
+                RecordSource::Final,
+                RawPixelFormat::Yuva444,
+                *frame_rate,
+                (1, 1)
+            )
+            .is_err());
+        }
+    }
+}
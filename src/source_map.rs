@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use wvr_data::shader::Shader;
+
+/// Where each file landed in a composed shader text, as (file, first line, line count) with
+/// lines counted from 1.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    segment_list: Vec<(PathBuf, usize, usize)>,
+}
+
+impl SourceMap {
+    pub fn get_file_list(&self) -> Vec<&Path> {
+        self.segment_list
+            .iter()
+            .map(|(file, _, _)| file.as_path())
+            .collect()
+    }
+
+    /// Returns the file path and line within that file for a line of the composed text.
+    pub fn resolve(&self, line: usize) -> Option<(&Path, usize)> {
+        self.segment_list
+            .iter()
+            .find(|(_, first_line, line_count)| {
+                line >= *first_line && line < first_line + line_count
+            })
+            .map(|(file, first_line, _)| (file.as_path(), line - first_line + 1))
+    }
+}
+
+/// Concatenates shader sources, each one starting on a new line, and records the resulting
+/// `SourceMap` every time the text is composed again.
+#[derive(Default)]
+pub struct SourceComposer {
+    shader_list: Vec<(PathBuf, Box<dyn Shader>)>,
+    text: String,
+    source_map: SourceMap,
+}
+
+impl SourceComposer {
+    /// Appends a shader, `file` being the path reported for lines coming from it.
+    pub fn push(&mut self, file: PathBuf, shader: Box<dyn Shader>) {
+        self.shader_list.push((file, shader));
+        self.compose();
+    }
+
+    pub fn get_source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    fn compose(&mut self) {
+        self.text.clear();
+        self.source_map.segment_list.clear();
+
+        let mut next_line = 1;
+        for (file, shader) in &self.shader_list {
+            let shader_text = shader.get_text();
+            let line_count = shader_text.lines().count();
+
+            self.text.push_str(shader_text);
+            if !shader_text.is_empty() && !shader_text.ends_with('\n') {
+                self.text.push('\n');
+            }
+
+            self.source_map
+                .segment_list
+                .push((file.clone(), next_line, line_count));
+            next_line += line_count;
+        }
+    }
+}
+
+impl Shader for SourceComposer {
+    fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Checks every shader even when one of them fails, the first error being returned once
+    /// the text was composed again.
+    fn check_changes(&mut self) -> Result<bool> {
+        let mut changed = false;
+        let mut first_error = None;
+        for (_, shader) in &mut self.shader_list {
+            match shader.check_changes() {
+                Ok(shader_changed) => changed |= shader_changed,
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        if changed {
+            self.compose();
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(changed),
+        }
+    }
+
+    fn update(&mut self) {
+        for (_, shader) in &mut self.shader_list {
+            shader.update();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TextShader;
+
+    use super::*;
+
+    fn build_composer(file_list: &[(&str, &str)]) -> SourceComposer {
+        let mut composer = SourceComposer::default();
+        for (file, text) in file_list {
+            composer.push(file.into(), Box::new(TextShader::new(text)));
+        }
+
+        composer
+    }
+
+    #[test]
+    fn resolves_lines_to_their_file() {
+        let composer = build_composer(&[("a.glsl", "a1\na2\n"), ("b.glsl", "b1\nb2\nb3\n")]);
+        let source_map = composer.get_source_map();
+
+        assert_eq!(composer.get_text(), "a1\na2\nb1\nb2\nb3\n");
+        assert_eq!(source_map.resolve(1), Some((Path::new("a.glsl"), 1)));
+        assert_eq!(source_map.resolve(2), Some((Path::new("a.glsl"), 2)));
+        assert_eq!(source_map.resolve(3), Some((Path::new("b.glsl"), 1)));
+        assert_eq!(source_map.resolve(5), Some((Path::new("b.glsl"), 3)));
+    }
+
+    #[test]
+    fn starts_each_file_on_a_new_line() {
+        let composer = build_composer(&[("a.glsl", "a1\na2"), ("b.glsl", "b1")]);
+        let source_map = composer.get_source_map();
+
+        assert_eq!(composer.get_text(), "a1\na2\nb1\n");
+        assert_eq!(source_map.resolve(2), Some((Path::new("a.glsl"), 2)));
+        assert_eq!(source_map.resolve(3), Some((Path::new("b.glsl"), 1)));
+    }
+
+    #[test]
+    fn skips_empty_files() {
+        let composer =
+            build_composer(&[("a.glsl", "a1\n"), ("empty.glsl", ""), ("b.glsl", "b1\n")]);
+        let source_map = composer.get_source_map();
+
+        assert_eq!(
+            source_map.get_file_list(),
+            vec![
+                Path::new("a.glsl"),
+                Path::new("empty.glsl"),
+                Path::new("b.glsl")
+            ]
+        );
+        assert_eq!(source_map.resolve(2), Some((Path::new("b.glsl"), 1)));
+    }
+
+    #[test]
+    fn maps_each_copy_of_a_duplicated_file() {
+        let composer = build_composer(&[
+            ("lib.glsl", "l1\n"),
+            ("main.glsl", "m1\n"),
+            ("lib.glsl", "l1\n"),
+        ]);
+        let source_map = composer.get_source_map();
+
+        assert_eq!(source_map.resolve(1), Some((Path::new("lib.glsl"), 1)));
+        assert_eq!(source_map.resolve(2), Some((Path::new("main.glsl"), 1)));
+        assert_eq!(source_map.resolve(3), Some((Path::new("lib.glsl"), 1)));
+    }
+
+    #[test]
+    fn rejects_lines_outside_the_text() {
+        let composer = build_composer(&[("a.glsl", "a1\na2\n")]);
+        let source_map = composer.get_source_map();
+
+        assert_eq!(source_map.resolve(0), None);
+        assert_eq!(source_map.resolve(3), None);
+        assert_eq!(SourceMap::default().resolve(1), None);
+    }
+
+    #[test]
+    fn recomposes_changed_shaders() {
+        let mut composer = SourceComposer::default();
+        composer.push("a.glsl".into(), Box::new(TextShader::new("a1\n")));
+        composer.push(
+            "b.glsl".into(),
+            Box::new(TextShader::changing("b1\n", "b1\nb2\n")),
+        );
+
+        assert!(composer.check_changes().unwrap());
+        assert_eq!(composer.get_text(), "a1\nb1\nb2\n");
+        assert_eq!(
+            composer.get_source_map().resolve(3),
+            Some((Path::new("b.glsl"), 2))
+        );
+
+        assert!(!composer.check_changes().unwrap());
+    }
+
+    #[test]
+    fn checks_every_shader_before_failing() {
+        let mut composer = SourceComposer::default();
+        composer.push(
+            "a.glsl".into(),
+            Box::new(TextShader::failing("a1\n", "a.glsl is missing")),
+        );
+        composer.push(
+            "b.glsl".into(),
+            Box::new(TextShader::changing("b1\n", "b2\n")),
+        );
+
+        let error = composer.check_changes().unwrap_err();
+        assert_eq!(error.to_string(), "a.glsl is missing");
+        assert_eq!(composer.get_text(), "a1\nb2\n");
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 
 use anyhow::Result;
@@ -10,18 +10,80 @@ use wvr_data::config::filter::FilterMode;
 use wvr_data::config::rendering::RenderStageConfig;
 use wvr_data::types::{Automation, BufferPrecision, DataHolder, InputSampler};
 
+use crate::filter::{DrawOptions, SamplerOptions};
 use crate::UniformHolder;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageResolution {
+    Absolute((u32, u32)),
+    /// Fraction of the view resolution for each axis.
+    Relative((f32, f32)),
+    /// Same size as the buffers of another stage of the render chain.
+    MatchStage(String),
+}
+
+impl Default for StageResolution {
+    fn default() -> Self {
+        StageResolution::Relative((1.0, 1.0))
+    }
+}
+
+pub fn compute_stage_resolution(
+    render_chain: &[Stage],
+    stage_index: usize,
+    view_resolution: (usize, usize),
+) -> (u32, u32) {
+    let view_resolution = (view_resolution.0 as u32, view_resolution.1 as u32);
+
+    let mut stage_index = stage_index;
+    // Bounded by the chain length so that stages matching each other fall back to the view size
+    for _ in 0..=render_chain.len() {
+        let stage = match render_chain.get(stage_index) {
+            Some(stage) => stage,
+            None => break,
+        };
+
+        match stage.get_resolution_policy() {
+            StageResolution::Absolute((width, height)) => {
+                return ((*width).max(1), (*height).max(1))
+            }
+            StageResolution::Relative((width_scale, height_scale)) => {
+                return (
+                    ((view_resolution.0 as f32 * width_scale).round() as u32).max(1),
+                    ((view_resolution.1 as f32 * height_scale).round() as u32).max(1),
+                )
+            }
+            StageResolution::MatchStage(stage_name) => {
+                match render_chain
+                    .iter()
+                    .position(|stage| stage.get_name() == stage_name)
+                {
+                    Some(matched_stage_index) => stage_index = matched_stage_index,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    view_resolution
+}
+
 pub struct Stage {
     name: String,
     filter: String,
     filter_mode_params: FilterMode,
     pub input_map: HashMap<String, InputSampler>,
+    pub feedback_input_list: HashSet<String>,
+    pub input_sampler_options_list: HashMap<String, SamplerOptions>,
     pub variable_list: HashMap<String, (DataHolder, Automation, Option<(String, DataHolder)>)>,
     pub uniform_list: HashMap<String, UniformHolder>,
     pub buffer_format: UncompressedFloatFormat,
+    pub resolution_policy: StageResolution,
+    pub history_depth: usize,
+    pub draw_options: DrawOptions,
 
     pub recreate_buffers: bool,
+    pub clear_requested: bool,
 }
 
 impl Stage {
@@ -69,10 +131,16 @@ impl Stage {
             filter: filter.to_string(),
             filter_mode_params,
             input_map,
+            feedback_input_list: HashSet::new(),
+            input_sampler_options_list: HashMap::new(),
             variable_list,
             uniform_list,
             buffer_format,
+            resolution_policy: StageResolution::default(),
+            history_depth: 1,
+            draw_options: DrawOptions::default(),
             recreate_buffers: true,
+            clear_requested: false,
         }
     }
 
@@ -92,6 +160,37 @@ impl Stage {
         &self.input_map
     }
 
+    pub fn is_feedback_input(&self, input_name: &str) -> bool {
+        self.feedback_input_list.contains(input_name)
+    }
+
+    pub fn get_input_sampler_options(&self, input_name: &str) -> SamplerOptions {
+        self.input_sampler_options_list
+            .get(input_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn get_draw_options(&self) -> DrawOptions {
+        self.draw_options
+    }
+
+    /// Blending, clearing and depth testing of the stage output. Depth buffers are allocated
+    /// when stages are rendered, the final stage rendered to a window uses the window depth buffer.
+    pub fn set_draw_options(&mut self, draw_options: DrawOptions) {
+        self.draw_options = draw_options;
+    }
+
+    /// Makes the next render clear the target as `ClearMode::Default` would, which resets
+    /// stages drawing over their previous frame.
+    pub fn request_clear(&mut self) {
+        self.clear_requested = true;
+    }
+
+    pub fn is_clear_requested(&self) -> bool {
+        self.clear_requested
+    }
+
     pub fn get_uniform_list(&self) -> &HashMap<String, UniformHolder> {
         &self.uniform_list
     }
@@ -100,6 +199,33 @@ impl Stage {
         self.buffer_format
     }
 
+    pub fn get_resolution_policy(&self) -> &StageResolution {
+        &self.resolution_policy
+    }
+
+    pub fn set_resolution_policy(&mut self, resolution_policy: &StageResolution) {
+        if *resolution_policy != self.resolution_policy {
+            self.resolution_policy = resolution_policy.clone();
+
+            self.recreate_buffers = true;
+        }
+    }
+
+    pub fn get_history_depth(&self) -> usize {
+        self.history_depth
+    }
+
+    /// Number of past frames kept for this stage, addressable as `stage_name@1` up to
+    /// `stage_name@history_depth`.
+    pub fn set_history_depth(&mut self, history_depth: usize) {
+        let history_depth = history_depth.max(1);
+        if history_depth != self.history_depth {
+            self.history_depth = history_depth;
+
+            self.recreate_buffers = true;
+        }
+    }
+
     pub fn set_precision(&mut self, precision: &BufferPrecision) {
         let new_buffer_format = match precision {
             BufferPrecision::U8 => UncompressedFloatFormat::U8U8U8U8,
@@ -204,6 +330,32 @@ impl Stage {
         self.input_map.insert(input_name.to_string(), input.clone());
     }
 
+    /// Feedback inputs always read the previous frame and are ignored when ordering stages.
+    pub fn set_input_feedback(&mut self, input_name: &str, feedback: bool) {
+        if feedback {
+            self.feedback_input_list.insert(input_name.to_string());
+        } else {
+            self.feedback_input_list.remove(input_name);
+        }
+    }
+
+    /// Wrap mode and anisotropy of an input, the default options are restored by passing
+    /// `SamplerOptions::default()`.
+    pub fn set_input_sampler_options(&mut self, input_name: &str, options: SamplerOptions) {
+        // GL rejects a maximum anisotropy below 1
+        let options = SamplerOptions {
+            anisotropy: options.anisotropy.max(1),
+            ..options
+        };
+
+        if options == SamplerOptions::default() {
+            self.input_sampler_options_list.remove(input_name);
+        } else {
+            self.input_sampler_options_list
+                .insert(input_name.to_string(), options);
+        }
+    }
+
     pub fn set_filter(&mut self, filter_name: &str) {
         self.filter = filter_name.to_string();
     }
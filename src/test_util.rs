@@ -0,0 +1,58 @@
+use anyhow::{Error, Result};
+
+use wvr_data::shader::Shader;
+
+/// Shader held in memory, whose changes and errors are scripted by the test.
+pub struct TextShader {
+    text: String,
+    next_text: Option<String>,
+    error_message: Option<String>,
+}
+
+impl TextShader {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            next_text: None,
+            error_message: None,
+        }
+    }
+
+    /// Reports `next_text` as a change on the next check.
+    pub fn changing(text: &str, next_text: &str) -> Self {
+        Self {
+            next_text: Some(next_text.to_owned()),
+            ..Self::new(text)
+        }
+    }
+
+    /// Fails every check with `error_message`.
+    pub fn failing(text: &str, error_message: &str) -> Self {
+        Self {
+            error_message: Some(error_message.to_owned()),
+            ..Self::new(text)
+        }
+    }
+}
+
+impl Shader for TextShader {
+    fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    fn check_changes(&mut self) -> Result<bool> {
+        if let Some(error_message) = &self.error_message {
+            return Err(Error::msg(error_message.clone()));
+        }
+
+        match self.next_text.take() {
+            Some(next_text) => {
+                self.text = next_text;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn update(&mut self) {}
+}
@@ -1,19 +1,80 @@
+use std::borrow::Cow;
 use std::convert::TryFrom;
+use std::fmt::{self, Write};
 
 use anyhow::{Context, Error, Result};
 
 use glium::backend::Facade;
+use glium::texture::ClientFormat;
 use glium::texture::RawImage2d;
+use glium::texture::SrgbFormat;
 use glium::texture::SrgbTexture2d;
 use glium::texture::Texture2d;
 use glium::texture::{DepthTexture2d, MipmapsOption};
+use glium::texture::{IntegralTexture2d, UnsignedTexture2d};
+use glium::texture::{UncompressedFloatFormat, UncompressedIntFormat, UncompressedUintFormat};
+
+use glium::CapabilitiesSource;
+use glium::Rect;
 
 use wvr_data::types::DataHolder;
 
+/// Texture holding an array, its kind decides the sampler type shaders have to declare.
+pub enum BufferTexture {
+    /// `sampler2D`, used for float arrays.
+    Float(DepthTexture2d),
+    /// `isampler2D` with `R32I` texels, used for integer arrays.
+    Integer(IntegralTexture2d),
+    /// `usampler2D` with `R8UI` texels, used for boolean and byte arrays.
+    Unsigned(UnsignedTexture2d),
+}
+
+impl BufferTexture {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            BufferTexture::Float(texture) => texture.dimensions(),
+            BufferTexture::Integer(texture) => texture.dimensions(),
+            BufferTexture::Unsigned(texture) => texture.dimensions(),
+        }
+    }
+}
+
+/// Value of the `<name>_layout` uniform bound along with an array input: texture width,
+/// texture height and array length. Shaders pass it to the `wvr_buffer_fetch` helpers of
+/// `wvr_buffer.glsl`, which filters list among their shader files without installing it, see
+/// `libs::LIB_FILE_LIST`.
+pub fn get_buffer_layout(dimensions: (u32, u32), length: usize) -> (i32, i32, i32) {
+    (dimensions.0 as i32, dimensions.1 as i32, length as i32)
+}
+
+/// Splits an array into rows no longer than the maximum texture size, the last row being
+/// padded, so that arrays of any length fit in a texture.
+fn pack_array<T: Copy + Default>(display: &dyn Facade, array: &[T]) -> Vec<Vec<T>> {
+    let max_width = display.get_context().get_capabilities().max_texture_size;
+
+    pack_array_rows(array, max_width.max(1) as usize)
+}
+
+fn pack_array_rows<T: Copy + Default>(array: &[T], max_width: usize) -> Vec<Vec<T>> {
+    let width = array.len().clamp(1, max_width);
+
+    let mut row_list: Vec<Vec<T>> = array.chunks(width).map(<[T]>::to_vec).collect();
+    match row_list.last_mut() {
+        Some(last_row) => last_row.resize(width, T::default()),
+        None => row_list.push(vec![T::default(); width]),
+    }
+
+    row_list
+}
+
 pub enum UniformHolder {
-    Buffer((DepthTexture2d, usize)),
-    Texture((Texture2d, (u32, u32))),
-    SrgbTexture((SrgbTexture2d, (u32, u32))),
+    /// Array packed into a texture along with its length.
+    Buffer((BufferTexture, usize)),
+    /// Array of vectors, one RGBA texel per element with unused channels set to 0.
+    VectorBuffer((Texture2d, usize)),
+    /// Texture along with its resolution and storage format.
+    Texture((Texture2d, (u32, u32), UncompressedFloatFormat)),
+    SrgbTexture((SrgbTexture2d, (u32, u32), SrgbFormat)),
 
     Float(f32),
     Float2((f32, f32)),
@@ -21,6 +82,9 @@ pub enum UniformHolder {
     Float4((f32, f32, f32, f32)),
 
     Integer(i32),
+    Integer2((i32, i32)),
+    Integer3((i32, i32, i32)),
+    Integer4((i32, i32, i32, i32)),
     Bool(bool),
 
     Mat2([[f32; 2]; 2]),
@@ -28,11 +92,392 @@ pub enum UniformHolder {
     Mat4([[f32; 4]; 4]),
 }
 
+fn build_vector_buffer(
+    display: &dyn Facade,
+    array: Vec<(f32, f32, f32, f32)>,
+) -> Result<UniformHolder> {
+    let length = array.len();
+    let texture = Texture2d::with_format(
+        display,
+        pack_array(display, &array),
+        UncompressedFloatFormat::F32F32F32F32,
+        MipmapsOption::NoMipmap,
+    )
+    .context("Failed to build buffer from vector array")?;
+
+    Ok(UniformHolder::VectorBuffer((texture, length)))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum AlphaMode {
+    /// Texels are uploaded as provided.
+    #[default]
+    Straight,
+    /// Colors are multiplied by alpha on upload, for stages blending sources with
+    /// `One, OneMinusSourceAlpha`.
+    Premultiplied,
+}
+
+/// Encoding of the bytes of a `DataHolder::Texture` input, channels following each other
+/// in every texel.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum TexelFormat {
+    /// One byte per channel.
+    #[default]
+    U8,
+    /// Half floats, two native-endian bytes per channel as written by `u16::to_ne_bytes`.
+    F16,
+    /// Floats, four native-endian bytes per channel as written by `f32::to_ne_bytes`.
+    F32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum FloatPrecision {
+    F16,
+    #[default]
+    F32,
+}
+
+/// Upload settings of a texture input.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TextureOptions {
+    pub alpha_mode: AlphaMode,
+    /// Encoding of the texture data, float data keeping values out of the [0, 1] range.
+    pub texel_format: TexelFormat,
+    /// Storage of float texture data, 8 bits data keeps 8 bits per channel.
+    pub float_precision: FloatPrecision,
+}
+
+/// Number of channels of texture data: 1 for gray, 2 for gray and alpha, 3 for RGB and 4
+/// for RGBA.
+fn get_channel_count<T>(resolution: (u32, u32), texture_data: &[T]) -> Result<usize> {
+    let texel_count = resolution.0 as usize * resolution.1 as usize;
+
+    match texture_data.len().checked_div(texel_count) {
+        Some(channel_count @ 1..=4) if channel_count * texel_count == texture_data.len() => {
+            Ok(channel_count)
+        }
+        _ => Err(Error::msg(format!(
+            "Texture data of {} values does not match a {}x{} texture",
+            texture_data.len(),
+            resolution.0,
+            resolution.1
+        ))),
+    }
+}
+
+fn get_texture_format(channel_count: usize) -> (ClientFormat, UncompressedFloatFormat) {
+    match channel_count {
+        3 => (ClientFormat::U8U8U8, UncompressedFloatFormat::U8U8U8),
+        _ => (ClientFormat::U8U8U8U8, UncompressedFloatFormat::U8U8U8U8),
+    }
+}
+
+fn get_float_texture_format(
+    channel_count: usize,
+    precision: FloatPrecision,
+) -> (ClientFormat, UncompressedFloatFormat) {
+    match (channel_count, precision) {
+        (1, FloatPrecision::F16) => (ClientFormat::F32, UncompressedFloatFormat::F16),
+        (1, FloatPrecision::F32) => (ClientFormat::F32, UncompressedFloatFormat::F32),
+        (2, FloatPrecision::F16) => (ClientFormat::F32F32, UncompressedFloatFormat::F16F16),
+        (2, FloatPrecision::F32) => (ClientFormat::F32F32, UncompressedFloatFormat::F32F32),
+        (3, FloatPrecision::F16) => (ClientFormat::F32F32F32, UncompressedFloatFormat::F16F16F16),
+        (3, FloatPrecision::F32) => (ClientFormat::F32F32F32, UncompressedFloatFormat::F32F32F32),
+        (_, FloatPrecision::F16) => (
+            ClientFormat::F32F32F32F32,
+            UncompressedFloatFormat::F16F16F16F16,
+        ),
+        (_, FloatPrecision::F32) => (
+            ClientFormat::F32F32F32F32,
+            UncompressedFloatFormat::F32F32F32F32,
+        ),
+    }
+}
+
+fn get_srgb_format(channel_count: usize) -> SrgbFormat {
+    match channel_count {
+        3 => SrgbFormat::U8U8U8,
+        _ => SrgbFormat::U8U8U8U8,
+    }
+}
+
+fn premultiply(value: u8, alpha: u8) -> u8 {
+    ((value as u16 * alpha as u16 + 127) / 255) as u8
+}
+
+/// Builds the image uploaded for texture data along with its channel count. Gray data is
+/// expanded to RGB and gray with alpha to RGBA, so shaders read it like color data.
+fn build_texture_image(
+    resolution: (u32, u32),
+    texture_data: &[u8],
+    alpha_mode: AlphaMode,
+) -> Result<(RawImage2d<'_, u8>, usize)> {
+    let channel_count = get_channel_count(resolution, texture_data)?;
+
+    let data: Cow<[u8]> = match (channel_count, alpha_mode) {
+        (2, AlphaMode::Premultiplied) => Cow::Owned(
+            texture_data
+                .chunks_exact(2)
+                .flat_map(|texel| [premultiply(texel[0], texel[1]), texel[1]])
+                .collect(),
+        ),
+        (4, AlphaMode::Premultiplied) => Cow::Owned(
+            texture_data
+                .chunks_exact(4)
+                .flat_map(|texel| {
+                    [
+                        premultiply(texel[0], texel[3]),
+                        premultiply(texel[1], texel[3]),
+                        premultiply(texel[2], texel[3]),
+                        texel[3],
+                    ]
+                })
+                .collect(),
+        ),
+        _ => Cow::Borrowed(texture_data),
+    };
+
+    let (data, uploaded_channel_count): (Cow<[u8]>, usize) = match channel_count {
+        1 => (
+            Cow::Owned(
+                data.iter()
+                    .flat_map(|&value| [value, value, value])
+                    .collect(),
+            ),
+            3,
+        ),
+        2 => (
+            Cow::Owned(
+                data.chunks_exact(2)
+                    .flat_map(|texel| [texel[0], texel[0], texel[0], texel[1]])
+                    .collect(),
+            ),
+            4,
+        ),
+        _ => (data, channel_count),
+    };
+
+    Ok((
+        RawImage2d {
+            data,
+            width: resolution.0,
+            height: resolution.1,
+            format: get_texture_format(uploaded_channel_count).0,
+        },
+        uploaded_channel_count,
+    ))
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = half & 0x3ff;
+
+    match exponent {
+        0 => sign * mantissa as f32 * 2f32.powi(-24),
+        0x1f if mantissa == 0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa as f32 / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// Reads the floats of texture data encoded with `texel_format`.
+fn decode_float_texels(texture_data: &[u8], texel_format: TexelFormat) -> Result<Vec<f32>> {
+    let value_size = match texel_format {
+        TexelFormat::U8 => return Ok(texture_data.iter().map(|&x| x as f32 / 255.0).collect()),
+        TexelFormat::F16 => 2,
+        TexelFormat::F32 => 4,
+    };
+
+    let value_list = texture_data.chunks_exact(value_size);
+    if !value_list.remainder().is_empty() {
+        anyhow::bail!(
+            "Texture data of {} bytes is not made of {:?} values",
+            texture_data.len(),
+            texel_format
+        );
+    }
+
+    Ok(value_list
+        .map(|bytes| match bytes {
+            [a, b] => f16_to_f32(u16::from_ne_bytes([*a, *b])),
+            [a, b, c, d] => f32::from_ne_bytes([*a, *b, *c, *d]),
+            _ => unreachable!(),
+        })
+        .collect())
+}
+
+/// Builds the image uploaded for float texture data along with the texture format to
+/// store it in. Values are kept as provided, out of the [0, 1] range included.
+fn build_float_texture_image(
+    resolution: (u32, u32),
+    texture_data: &[f32],
+    options: TextureOptions,
+) -> Result<(RawImage2d<'_, f32>, UncompressedFloatFormat)> {
+    let channel_count = get_channel_count(resolution, texture_data)?;
+    let (client_format, texture_format) =
+        get_float_texture_format(channel_count, options.float_precision);
+
+    let data: Cow<[f32]> = match (channel_count, options.alpha_mode) {
+        (2, AlphaMode::Premultiplied) => Cow::Owned(
+            texture_data
+                .chunks_exact(2)
+                .flat_map(|texel| [texel[0] * texel[1], texel[1]])
+                .collect(),
+        ),
+        (4, AlphaMode::Premultiplied) => Cow::Owned(
+            texture_data
+                .chunks_exact(4)
+                .flat_map(|texel| {
+                    [
+                        texel[0] * texel[3],
+                        texel[1] * texel[3],
+                        texel[2] * texel[3],
+                        texel[3],
+                    ]
+                })
+                .collect(),
+        ),
+        _ => Cow::Borrowed(texture_data),
+    };
+
+    Ok((
+        RawImage2d {
+            data,
+            width: resolution.0,
+            height: resolution.1,
+            format: client_format,
+        },
+        texture_format,
+    ))
+}
+
+/// Name of a `DataHolder` variant, which its debug output starts with. Formatting stops at
+/// the variant fields, so large values are not formatted.
+fn get_variant_name(uniform: &DataHolder) -> String {
+    struct VariantName(String);
+
+    impl fmt::Write for VariantName {
+        fn write_str(&mut self, text: &str) -> fmt::Result {
+            match text.find(|c: char| !c.is_alphanumeric() && c != '_') {
+                Some(name_end) => {
+                    self.0.push_str(&text[..name_end]);
+                    Err(fmt::Error)
+                }
+                None => {
+                    self.0.push_str(text);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    let mut variant_name = VariantName(String::new());
+    let _ = write!(variant_name, "{:?}", uniform);
+
+    variant_name.0
+}
+
+impl UniformHolder {
+    /// Uploads a new value into the texture already held when it has the same kind, size
+    /// and format, which avoids allocating a texture per frame for video inputs.
+    /// Returns `false` when the holder has to be rebuilt with `try_from` instead.
+    pub fn write(
+        &self,
+        uniform: &DataHolder,
+        generate_mipmaps: bool,
+        options: TextureOptions,
+    ) -> bool {
+        let rect = |resolution: &(u32, u32)| Rect {
+            left: 0,
+            bottom: 0,
+            width: resolution.0,
+            height: resolution.1,
+        };
+
+        let written = match (self, uniform) {
+            (
+                UniformHolder::Texture((texture, resolution, format)),
+                DataHolder::Texture((new_resolution, texture_data)),
+            ) if resolution == new_resolution && options.texel_format == TexelFormat::U8 => {
+                match build_texture_image(*resolution, texture_data, options.alpha_mode) {
+                    Ok((image, channel_count))
+                        if get_texture_format(channel_count).1 == *format =>
+                    {
+                        texture.write(rect(resolution), image);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            (
+                UniformHolder::Texture((texture, resolution, format)),
+                DataHolder::Texture((new_resolution, texture_data)),
+            ) if resolution == new_resolution => {
+                match decode_float_texels(texture_data, options.texel_format) {
+                    Ok(float_data) => {
+                        match build_float_texture_image(*resolution, &float_data, options) {
+                            Ok((image, new_format)) if new_format == *format => {
+                                texture.write(rect(resolution), image);
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    Err(_) => false,
+                }
+            }
+            (
+                UniformHolder::SrgbTexture((texture, resolution, format)),
+                DataHolder::SrgbTexture((new_resolution, texture_data)),
+            ) if resolution == new_resolution => {
+                match build_texture_image(*resolution, texture_data, options.alpha_mode) {
+                    Ok((image, channel_count)) if get_srgb_format(channel_count) == *format => {
+                        texture.write(rect(resolution), image);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+
+        if written && generate_mipmaps {
+            unsafe {
+                match self {
+                    UniformHolder::Texture((texture, _, _)) => texture.generate_mipmaps(),
+                    UniformHolder::SrgbTexture((texture, _, _)) => texture.generate_mipmaps(),
+                    _ => (),
+                }
+            }
+        }
+
+        written
+    }
+}
+
 impl TryFrom<(&dyn Facade, &DataHolder, bool)> for UniformHolder {
     type Error = Error;
 
     fn try_from(uniform: (&dyn Facade, &DataHolder, bool)) -> Result<UniformHolder> {
         let (display, uniform, generate_mipmaps) = uniform;
+        UniformHolder::try_from((
+            display,
+            uniform,
+            generate_mipmaps,
+            TextureOptions::default(),
+        ))
+    }
+}
+
+impl TryFrom<(&dyn Facade, &DataHolder, bool, TextureOptions)> for UniformHolder {
+    type Error = Error;
+
+    fn try_from(
+        uniform: (&dyn Facade, &DataHolder, bool, TextureOptions),
+    ) -> Result<UniformHolder> {
+        let (display, uniform, generate_mipmaps, options) = uniform;
         match uniform {
             DataHolder::Float(value) => Ok(UniformHolder::Float(*value as f32)),
             DataHolder::Float2(value) => Ok(UniformHolder::Float2((value[0], value[1]))),
@@ -41,11 +486,36 @@ impl TryFrom<(&dyn Facade, &DataHolder, bool)> for UniformHolder {
                 value[0], value[1], value[2], value[3],
             ))),
             DataHolder::Int(value) => Ok(UniformHolder::Integer(*value as i32)),
+            DataHolder::Int2(value) => Ok(UniformHolder::Integer2((value[0], value[1]))),
+            DataHolder::Int3(value) => Ok(UniformHolder::Integer3((value[0], value[1], value[2]))),
+            DataHolder::Int4(value) => Ok(UniformHolder::Integer4((
+                value[0], value[1], value[2], value[3],
+            ))),
             DataHolder::Bool(value) => Ok(UniformHolder::Bool(*value)),
+            DataHolder::Texture((resolution, texture_data))
+                if options.texel_format == TexelFormat::U8 =>
+            {
+                let (image, channel_count) =
+                    build_texture_image(*resolution, texture_data, options.alpha_mode)?;
+                let format = get_texture_format(channel_count).1;
+                let texture =
+                    Texture2d::with_format(display, image, format, MipmapsOption::EmptyMipmaps)
+                        .context("Failed to build texture from texture data")?;
+
+                if generate_mipmaps {
+                    unsafe {
+                        texture.generate_mipmaps();
+                    }
+                }
+
+                Ok(UniformHolder::Texture((texture, *resolution, format)))
+            }
             DataHolder::Texture((resolution, texture_data)) => {
-                let image = RawImage2d::from_raw_rgb(texture_data.clone(), *resolution);
-                let texture = Texture2d::with_mipmaps(display, image, MipmapsOption::EmptyMipmaps)
-                    .context("Failed to build texture from texture data")?;
+                let float_data = decode_float_texels(texture_data, options.texel_format)?;
+                let (image, format) = build_float_texture_image(*resolution, &float_data, options)?;
+                let texture =
+                    Texture2d::with_format(display, image, format, MipmapsOption::EmptyMipmaps)
+                        .context("Failed to build texture from float texture data")?;
 
                 if generate_mipmaps {
                     unsafe {
@@ -53,12 +523,14 @@ impl TryFrom<(&dyn Facade, &DataHolder, bool)> for UniformHolder {
                     }
                 }
 
-                Ok(UniformHolder::Texture((texture, *resolution)))
+                Ok(UniformHolder::Texture((texture, *resolution, format)))
             }
             DataHolder::SrgbTexture((resolution, texture_data)) => {
-                let image = RawImage2d::from_raw_rgb(texture_data.clone(), *resolution);
+                let (image, channel_count) =
+                    build_texture_image(*resolution, texture_data, options.alpha_mode)?;
+                let format = get_srgb_format(channel_count);
                 let texture =
-                    SrgbTexture2d::with_mipmaps(display, image, MipmapsOption::EmptyMipmaps)
+                    SrgbTexture2d::with_format(display, image, format, MipmapsOption::EmptyMipmaps)
                         .context("Failed to build texture from texture data")?;
 
                 if generate_mipmaps {
@@ -67,40 +539,251 @@ impl TryFrom<(&dyn Facade, &DataHolder, bool)> for UniformHolder {
                     }
                 }
 
-                Ok(UniformHolder::SrgbTexture((texture, *resolution)))
+                Ok(UniformHolder::SrgbTexture((texture, *resolution, format)))
             }
             DataHolder::FloatArray(array) => Ok(UniformHolder::Buffer((
-                DepthTexture2d::new(display, vec![array.clone()])
-                    .context("Failed to build buffer from float array")?,
+                BufferTexture::Float(
+                    DepthTexture2d::new(display, pack_array(display, array))
+                        .context("Failed to build buffer from float array")?,
+                ),
                 array.len(),
             ))),
             DataHolder::BoolArray(array) => Ok(UniformHolder::Buffer((
-                DepthTexture2d::new(
-                    display,
-                    vec![array.iter().map(|&x| if x { 1.0 } else { 0.0 }).collect()],
-                )
-                .context("Failed to build buffer from boolean array")?,
+                BufferTexture::Unsigned(
+                    UnsignedTexture2d::with_format(
+                        display,
+                        pack_array(
+                            display,
+                            &array.iter().map(|&x| x as u8).collect::<Vec<u8>>(),
+                        ),
+                        UncompressedUintFormat::U8,
+                        MipmapsOption::NoMipmap,
+                    )
+                    .context("Failed to build buffer from boolean array")?,
+                ),
                 array.len(),
             ))),
 
             DataHolder::IntArray(array) => Ok(UniformHolder::Buffer((
-                DepthTexture2d::new(
-                    display,
-                    vec![array.iter().map(|&x| x as f32 / 2f32.powf(32.0)).collect()],
-                )
-                .context("Failed to build buffer from integer array")?,
+                BufferTexture::Integer(
+                    IntegralTexture2d::with_format(
+                        display,
+                        pack_array(display, array),
+                        UncompressedIntFormat::I32,
+                        MipmapsOption::NoMipmap,
+                    )
+                    .context("Failed to build buffer from integer array")?,
+                ),
                 array.len(),
             ))),
 
             DataHolder::ByteArray(array) => Ok(UniformHolder::Buffer((
-                DepthTexture2d::new(
-                    display,
-                    vec![array.iter().map(|&x| x as f32 / 255.0).collect()],
-                )
-                .context("Failed to build buffer from byte array")?,
+                BufferTexture::Unsigned(
+                    UnsignedTexture2d::with_format(
+                        display,
+                        pack_array(display, array),
+                        UncompressedUintFormat::U8,
+                        MipmapsOption::NoMipmap,
+                    )
+                    .context("Failed to build buffer from byte array")?,
+                ),
                 array.len(),
             ))),
-            _ => unimplemented!(),
+            DataHolder::Float2Array(array) => build_vector_buffer(
+                display,
+                array
+                    .iter()
+                    .map(|value| (value[0], value[1], 0.0, 0.0))
+                    .collect(),
+            ),
+            DataHolder::Float3Array(array) => build_vector_buffer(
+                display,
+                array
+                    .iter()
+                    .map(|value| (value[0], value[1], value[2], 0.0))
+                    .collect(),
+            ),
+            DataHolder::Float4Array(array) => build_vector_buffer(
+                display,
+                array
+                    .iter()
+                    .map(|value| (value[0], value[1], value[2], value[3]))
+                    .collect(),
+            ),
+            DataHolder::String(_) => Err(Error::msg(
+                "String values cannot be used as uniforms and are ignored",
+            )),
+            // Variants added to wvr-data later on are reported instead of taking the renderer down
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::msg(format!(
+                "Unsupported uniform value type: {}",
+                get_variant_name(uniform)
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_short_arrays_in_one_row() {
+        assert_eq!(pack_array_rows(&[1, 2, 3], 8), vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn splits_long_arrays_and_pads_the_last_row() {
+        assert_eq!(
+            pack_array_rows(&[1, 2, 3, 4, 5], 2),
+            vec![vec![1, 2], vec![3, 4], vec![5, 0]]
+        );
+        assert_eq!(
+            pack_array_rows(&[1, 2, 3, 4], 2),
+            vec![vec![1, 2], vec![3, 4]]
+        );
+    }
+
+    #[test]
+    fn packs_empty_arrays_in_one_texel() {
+        assert_eq!(pack_array_rows::<f32>(&[], 8), vec![vec![0.0]]);
+    }
+
+    #[test]
+    fn describes_buffer_layout() {
+        let row_list = pack_array_rows(&[1; 5], 2);
+
+        assert_eq!(
+            get_buffer_layout((row_list[0].len() as u32, row_list.len() as u32), 5),
+            (2, 3, 5)
+        );
+    }
+
+    #[test]
+    fn names_data_variants() {
+        assert_eq!(get_variant_name(&DataHolder::Bool(true)), "Bool");
+        assert_eq!(
+            get_variant_name(&DataHolder::FloatArray(vec![0.0; 4096])),
+            "FloatArray"
+        );
+        assert_eq!(
+            get_variant_name(&DataHolder::String("text".to_owned())),
+            "String"
+        );
+    }
+
+    #[test]
+    fn counts_texture_channels() {
+        for channel_count in 1..=4 {
+            assert_eq!(
+                get_channel_count((3, 2), &vec![0u8; 6 * channel_count]).unwrap(),
+                channel_count
+            );
         }
     }
+
+    #[test]
+    fn rejects_mismatched_texture_data() {
+        assert!(get_channel_count((3, 2), &[0u8; 0]).is_err());
+        assert!(get_channel_count((3, 2), &[0u8; 7]).is_err());
+        assert!(get_channel_count((3, 2), &[0u8; 30]).is_err());
+        assert!(get_channel_count((0, 2), &[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn expands_gray_texture_data() {
+        let (image, channel_count) =
+            build_texture_image((2, 1), &[10, 20], AlphaMode::Straight).unwrap();
+        assert_eq!(channel_count, 3);
+        assert_eq!(image.format, ClientFormat::U8U8U8);
+        assert_eq!(image.data.as_ref(), &[10, 10, 10, 20, 20, 20]);
+
+        let (image, channel_count) =
+            build_texture_image((1, 1), &[10, 128], AlphaMode::Straight).unwrap();
+        assert_eq!(channel_count, 4);
+        assert_eq!(image.format, ClientFormat::U8U8U8U8);
+        assert_eq!(image.data.as_ref(), &[10, 10, 10, 128]);
+    }
+
+    #[test]
+    fn premultiplies_texture_data() {
+        let (image, _) =
+            build_texture_image((1, 1), &[255, 128], AlphaMode::Premultiplied).unwrap();
+        assert_eq!(image.data.as_ref(), &[128, 128, 128, 128]);
+
+        let (image, channel_count) =
+            build_texture_image((1, 1), &[255, 100, 0, 0], AlphaMode::Premultiplied).unwrap();
+        assert_eq!(channel_count, 4);
+        assert_eq!(image.data.as_ref(), &[0, 0, 0, 0]);
+    }
+
+    fn encode_floats(value_list: &[f32]) -> Vec<u8> {
+        value_list
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_half_floats() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn decodes_float_texels() {
+        assert_eq!(
+            decode_float_texels(&encode_floats(&[-1.5, 0.0, 12.25]), TexelFormat::F32).unwrap(),
+            vec![-1.5, 0.0, 12.25]
+        );
+
+        let half_data: Vec<u8> = [0x3c00u16, 0x4900]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        assert_eq!(
+            decode_float_texels(&half_data, TexelFormat::F16).unwrap(),
+            vec![1.0, 10.0]
+        );
+
+        assert!(decode_float_texels(&[0; 6], TexelFormat::F32).is_err());
+        assert!(decode_float_texels(&[0; 3], TexelFormat::F16).is_err());
+    }
+
+    #[test]
+    fn builds_float_texture_images() {
+        let float_data =
+            decode_float_texels(&encode_floats(&[4.0, -1.0, 0.5, 0.5]), TexelFormat::F32).unwrap();
+
+        let (image, format) =
+            build_float_texture_image((1, 1), &float_data, TextureOptions::default()).unwrap();
+        assert_eq!(format, UncompressedFloatFormat::F32F32F32F32);
+        assert_eq!(image.format, ClientFormat::F32F32F32F32);
+        assert_eq!(image.data.as_ref(), &[4.0, -1.0, 0.5, 0.5]);
+
+        let options = TextureOptions {
+            alpha_mode: AlphaMode::Premultiplied,
+            texel_format: TexelFormat::F32,
+            float_precision: FloatPrecision::F16,
+        };
+        let (image, format) = build_float_texture_image((1, 1), &float_data, options).unwrap();
+        assert_eq!(format, UncompressedFloatFormat::F16F16F16F16);
+        assert_eq!(image.data.as_ref(), &[2.0, -0.5, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn keeps_float_channel_count() {
+        let (image, format) =
+            build_float_texture_image((2, 1), &[0.25, 8.0], TextureOptions::default()).unwrap();
+        assert_eq!(format, UncompressedFloatFormat::F32);
+        assert_eq!(image.format, ClientFormat::F32);
+
+        assert!(build_float_texture_image((2, 2), &[0.0; 3], TextureOptions::default()).is_err());
+    }
 }